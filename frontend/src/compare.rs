@@ -0,0 +1,290 @@
+use accounting::Accounting;
+use serde_json::Value;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+use crate::fetch_json;
+
+// (field as the api expects it, label, shown in millions)
+const FIELDS: [(&str, &str, bool); 10] = [
+    ("income.revenue", "Revenue", true),
+    ("income.netIncome", "Net Income", true),
+    ("income.eps", "EPS", false),
+    ("income.grossProfitRatio", "Gross Margin", false),
+    ("cash_flow.freeCashFlow", "Free Cash Flow", true),
+    ("ratios.returnOnEquity", "Return on Equity", false),
+    ("ratios.netProfitMargin", "Net Margin", false),
+    ("ratios.debtEquityRatio", "Debt to Equity", false),
+    ("key_metrics.peRatio", "P/E", false),
+    ("key_metrics.freeCashFlowPerShare", "FCF per Share", false),
+];
+
+const COLORS: [&str; 10] = [
+    "#1095c1", "#e8590c", "#2f9e44", "#c2255c", "#7048e8", "#f59f00", "#0c8599", "#5c940d",
+    "#862e9c", "#495057",
+];
+
+#[function_component(ComparePage)]
+pub fn compare_page() -> Html {
+    let symbols = use_state(initial_symbols);
+    let fields = use_state(|| {
+        vec![
+            "income.revenue".to_string(),
+            "ratios.returnOnEquity".to_string(),
+        ]
+    });
+    let period = use_state(|| "annual".to_string());
+    let data = use_state(|| None::<Value>);
+    let error = use_state(|| None::<String>);
+    let loading = use_state(|| false);
+
+    let on_symbols = {
+        let symbols = symbols.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e
+                .target()
+                .and_then(|t| t.dyn_into::<HtmlInputElement>().ok())
+            {
+                symbols.set(input.value());
+            }
+        })
+    };
+
+    let on_period = {
+        let period = period.clone();
+        Callback::from(move |e: Event| {
+            if let Some(select) = e
+                .target()
+                .and_then(|t| t.dyn_into::<HtmlSelectElement>().ok())
+            {
+                period.set(select.value());
+            }
+        })
+    };
+
+    let on_compare = {
+        let symbols = symbols.clone();
+        let fields = fields.clone();
+        let period = period.clone();
+        let data = data.clone();
+        let error = error.clone();
+        let loading = loading.clone();
+
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+
+            let url = format!(
+                "/api/compare?symbols={}&fields={}&period={}",
+                symbols.replace(' ', ""),
+                fields.join(","),
+                *period
+            );
+            let data = data.clone();
+            let error = error.clone();
+            let loading = loading.clone();
+
+            loading.set(true);
+            spawn_local(async move {
+                match fetch_json::<Value>(&url).await {
+                    Ok(result) => {
+                        data.set(Some(result));
+                        error.set(None);
+                    }
+                    Err(e) => error.set(Some(e)),
+                }
+                loading.set(false);
+            });
+        })
+    };
+
+    html! {
+        <section class={classes!("container")}>
+            <h1>{"Compare"}</h1>
+            <form onsubmit={on_compare}>
+                <div class={classes!("grid")}>
+                    <label>
+                        {"Symbols"}
+                        <input type="text" placeholder="AAPL, MSFT, GOOGL" value={(*symbols).clone()} oninput={on_symbols} />
+                    </label>
+                    <label>
+                        {"Period"}
+                        <select onchange={on_period}>
+                            <option value="annual" selected={*period == "annual"}>{"Annual"}</option>
+                            <option value="quarter" selected={*period == "quarter"}>{"Quarterly"}</option>
+                        </select>
+                    </label>
+                </div>
+                <fieldset>
+                    {FIELDS.iter().map(|(field, label, _)| {
+                        let checked = fields.contains(&field.to_string());
+                        let on_toggle = {
+                            let fields = fields.clone();
+                            let field = field.to_string();
+                            Callback::from(move |_: MouseEvent| {
+                                let mut chosen = (*fields).clone();
+                                match chosen.iter().position(|f| *f == field) {
+                                    Some(i) => {
+                                        chosen.remove(i);
+                                    }
+                                    None => chosen.push(field.clone()),
+                                }
+                                fields.set(chosen);
+                            })
+                        };
+
+                        html! {
+                            <label>
+                                <input type="checkbox" {checked} onclick={on_toggle} />
+                                {label}
+                            </label>
+                        }
+                    }).collect::<Html>()}
+                </fieldset>
+                <button type="submit" aria-busy={loading.to_string()} disabled={fields.is_empty()}>{"Compare"}</button>
+            </form>
+            if let Some(e) = error.as_ref() {
+                <p>{e}</p>
+            }
+            if let Some(comparison) = data.as_ref() {
+                {comparison_view(comparison)}
+            }
+        </section>
+    }
+}
+
+fn comparison_view(comparison: &Value) -> Html {
+    let labels = comparison["labels"].as_array().cloned().unwrap_or_default();
+    let series = comparison["series"].as_array().cloned().unwrap_or_default();
+
+    let mut ac = Accounting::new_from("$", 2);
+    ac.set_format_positive("{v}");
+    ac.set_format_negative("({v})");
+    ac.set_format_zero("--");
+
+    series
+        .iter()
+        .map(|s| {
+            let field = format!(
+                "{}.{}",
+                s["field"]["source"].as_str().unwrap_or_default(),
+                s["field"]["name"].as_str().unwrap_or_default()
+            );
+            let (label, millions) = FIELDS
+                .iter()
+                .find(|(f, _, _)| *f == field)
+                .map(|(_, label, millions)| (label.to_string(), *millions))
+                .unwrap_or((field.clone(), false));
+            let stocks = s["stocks"].as_array().cloned().unwrap_or_default();
+
+            html! {
+                <article>
+                    <h3>{&label}</h3>
+                    {chart(&labels, &stocks)}
+                    <figure>
+                        <table role="grid">
+                            <thead>
+                                <tr>
+                                    <th scope="col"></th>
+                                    {labels.iter().map(|l| html! {
+                                        <th scope="col"><nobr>{l.as_str()}</nobr></th>
+                                    }).collect::<Html>()}
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {stocks.iter().enumerate().map(|(i, stock)| {
+                                    let symbol = stock["symbol"].as_str().unwrap_or_default();
+                                    let values = stock["values"].as_array().cloned().unwrap_or_default();
+
+                                    html! {
+                                        <tr>
+                                            <th scope="row">
+                                                <a href={format!("/stock/{}", symbol)} style={format!("color: {}", COLORS[i % COLORS.len()])}>
+                                                    {symbol}
+                                                </a>
+                                            </th>
+                                            {values.iter().map(|v| html! {
+                                                <td>{
+                                                    match v.as_f64() {
+                                                        Some(v) if millions => ac.format_money(v / 1_000_000.0),
+                                                        Some(v) => format!("{:.2}", v),
+                                                        None => String::from("N/A"),
+                                                    }
+                                                }</td>
+                                            }).collect::<Html>()}
+                                        </tr>
+                                    }
+                                }).collect::<Html>()}
+                            </tbody>
+                        </table>
+                    </figure>
+                    if millions {
+                        <p><small>{" * In "}<strong>{"Millions"}</strong></small></p>
+                    }
+                </article>
+            }
+        })
+        .collect::<Html>()
+}
+
+// One line per stock, scaled to the range of all of them. Gaps are left where a stock did
+// not report the period.
+fn chart(labels: &[Value], stocks: &[Value]) -> Html {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    const PADDING: f64 = 10.0;
+
+    let values: Vec<f64> = stocks
+        .iter()
+        .flat_map(|s| s["values"].as_array().cloned().unwrap_or_default())
+        .filter_map(|v| v.as_f64())
+        .collect();
+
+    if values.is_empty() || labels.len() < 2 {
+        return html! {};
+    }
+
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+
+    let x = |i: usize| PADDING + i as f64 * (WIDTH - 2.0 * PADDING) / (labels.len() - 1) as f64;
+    let y = |v: f64| HEIGHT - PADDING - (v - min) / range * (HEIGHT - 2.0 * PADDING);
+
+    html! {
+        <svg viewBox={format!("0 0 {} {}", WIDTH, HEIGHT)} width="100%" role="img">
+            {stocks.iter().enumerate().map(|(i, stock)| {
+                let color = COLORS[i % COLORS.len()];
+                let values = stock["values"].as_array().cloned().unwrap_or_default();
+
+                // A new segment starts after every missing value
+                let mut segments: Vec<Vec<String>> = vec![vec![]];
+                for (j, v) in values.iter().enumerate() {
+                    match v.as_f64() {
+                        Some(v) => segments.last_mut().unwrap().push(format!("{:.1},{:.1}", x(j), y(v))),
+                        None => segments.push(vec![]),
+                    }
+                }
+
+                segments.iter().filter(|s| !s.is_empty()).map(|points| html! {
+                    <polyline points={points.join(" ")} fill="none" stroke={color} stroke-width="2" />
+                }).collect::<Html>()
+            }).collect::<Html>()}
+        </svg>
+    }
+}
+
+// /compare?symbols=AAPL,MSFT fills in the symbols
+fn initial_symbols() -> String {
+    web_sys::window()
+        .and_then(|w| w.location().search().ok())
+        .and_then(|search| {
+            search
+                .trim_start_matches('?')
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("symbols=").map(str::to_string))
+        })
+        .map(|symbols| symbols.replace("%2C", ","))
+        .unwrap_or_default()
+}
@@ -0,0 +1,121 @@
+use accounting::Accounting;
+use serde_json::Value;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+use crate::fetch_json;
+
+#[derive(Properties, PartialEq)]
+pub struct DividendsProps {
+    pub symbol: AttrValue,
+}
+
+#[function_component(Dividends)]
+pub fn dividends(DividendsProps { symbol }: &DividendsProps) -> Html {
+    let url = format!("/api/stock/{}/dividends", symbol);
+
+    let data = use_state(|| None);
+
+    {
+        let data = data.clone();
+        use_effect(move || {
+            if data.is_none() {
+                spawn_local(async move {
+                    let result: Value = fetch_json(&url).await.unwrap_or(Value::Null);
+                    data.set(Some(result));
+                });
+            }
+
+            || {}
+        });
+    }
+
+    let mut ac = Accounting::new_from("$", 2);
+    ac.set_format_positive("{v}");
+    ac.set_format_negative("({v})");
+    ac.set_format_zero("--");
+
+    match data.as_ref() {
+        None => html! {
+            <section>
+                <h3>{"Dividends"}</h3>
+                <progress></progress>
+            </section>
+        },
+        Some(history) => {
+            let years = history["years"].as_array().cloned().unwrap_or_default();
+
+            if years.is_empty() {
+                return html! {};
+            }
+
+            let rows: Vec<(&str, &str, bool)> = vec![
+                ("dividendsPaid", "Dividends Paid", true),
+                ("dividendPerShare", "Dividend per Share", false),
+                ("eps", "EPS", false),
+                ("payoutRatioEarnings", "Payout Ratio (Earnings)", false),
+                ("payoutRatioFreeCashFlow", "Payout Ratio (FCF)", false),
+                ("earningsCoverage", "Earnings Coverage", false),
+                ("freeCashFlowCoverage", "FCF Coverage", false),
+            ];
+
+            html! {
+                <section>
+                    <h3>{"Dividends"}</h3>
+                    <p>
+                        <u>{"Growth Streak:"}</u>{" "}
+                        {format!("{} years", history["growthStreak"].as_u64().unwrap_or_default())}
+                        {" - "}
+                        <u>{"Paid For:"}</u>{" "}
+                        {format!("{} years", history["paymentStreak"].as_u64().unwrap_or_default())}
+                    </p>
+                    <p>
+                        <u>{"Dividend Growth (CAGR):"}</u>{" "}
+                        {format!(
+                            "3y {} / 5y {} / 10y {}",
+                            percent(&history["cagr3Years"]),
+                            percent(&history["cagr5Years"]),
+                            percent(&history["cagr10Years"])
+                        )}
+                    </p>
+                    <table role="grid">
+                        <thead>
+                            <tr>
+                                <th scope="col"></th>
+                                {years.iter().map(|y| html! {
+                                    <th scope="col"><nobr>{y["date"].as_str()}</nobr></th>
+                                }).collect::<Html>()}
+                            </tr>
+                        </thead>
+                        <tbody>
+                            {rows.iter().map(|(name, label, millions)| html! {
+                                <tr>
+                                    <th scope="row"><nobr>{label}</nobr></th>
+                                    {years.iter().map(|y| html! {
+                                        <td>{
+                                            match y[*name].as_f64() {
+                                                Some(v) if *millions => ac.format_money(v / 1_000_000.0),
+                                                Some(v) if name.starts_with("payout") => format!("{:.1}%", v * 100.0),
+                                                Some(v) if name.ends_with("Coverage") => format!("{:.2}x", v),
+                                                Some(v) => ac.format_money(v),
+                                                None => String::from("N/A"),
+                                            }
+                                        }</td>
+                                    }).collect::<Html>()}
+                                </tr>
+                            }).collect::<Html>()}
+                        </tbody>
+                    </table>
+                    <p><small>{" * Dividends paid in "}<strong>{"Millions"}</strong></small></p>
+                </section>
+            }
+        }
+    }
+}
+
+fn percent(value: &Value) -> String {
+    match value.as_f64() {
+        Some(v) => format!("{:.1}%", v * 100.0),
+        None => String::from("N/A"),
+    }
+}
@@ -1,15 +1,24 @@
 use std::str;
 
-use gloo_net::http::Request;
+use futures::StreamExt;
+use gloo_net::eventsource::futures::EventSource;
+use gloo_net::http::{Request, Response};
+use gloo_timers::future::TimeoutFuture;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use stock::Stock;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
-use crate::screener::{ScreenCard, Screener};
+use crate::compare::ComparePage;
+use crate::screener::{JobProgress, LiveResults, RankingTable, RunHistory, ScreenCard, Screener};
+use crate::search::SymbolSearch;
 
+mod compare;
+mod dividends;
 mod screener;
+mod search;
 mod stock;
 
 #[derive(Clone, Routable, PartialEq)]
@@ -22,6 +31,8 @@ enum Route {
     ScreenPage { name: String },
     #[at("/stock/*symbol")]
     StockPage { symbol: String },
+    #[at("/compare")]
+    ComparePage,
 }
 
 fn switch(routes: Route) -> Html {
@@ -38,6 +49,9 @@ fn switch(routes: Route) -> Html {
         Route::StockPage { symbol } => html! {
             <Stock symbol={AttrValue::from(symbol)} />
         },
+        Route::ComparePage => html! {
+            <ComparePage />
+        },
     }
 }
 
@@ -62,11 +76,12 @@ fn app() -> Html {
                                         <ul role="listbox">
                                             <li><a href="/">{"Home"}</a></li>
                                             <li><a href="/screeners">{"Screeners"}</a></li>
+                                            <li><a href="/compare">{"Compare"}</a></li>
                                         </ul>
                                     </details>
                                 </li>
                                 <li>
-                                    <input type="search" id="search" name="search" placeholder="Search Symbols" />
+                                    <SymbolSearch />
                                 </li>
                             </ul>
                         </nav>
@@ -132,20 +147,101 @@ fn app() -> Html {
 
 #[function_component(ScreenersPage)]
 fn screeners_page() -> Html {
-    let screen = Screener {
-        id: 0,
-        name: AttrValue::from("Buffetology"),
-        decription: AttrValue::from("The Buffett strategy looks for stocks for an extremely 
-        long term horizon and combines both value and quality factors to identify stocks of companies with solid businesses 
-        and profitability and sound financials that trade at an attractive prices. Only stocks with consistent long term track records can 
-        pass this methodology."),
-    };
+    let data = use_state(|| None);
+    let error = use_state(|| None::<String>);
+
+    {
+        let data = data.clone();
+        let error = error.clone();
+        use_effect(move || {
+            if data.is_none() && error.is_none() {
+                spawn_local(async move {
+                    match fetch_json::<Vec<Value>>("/api/screeners").await {
+                        Ok(result) => data.set(Some(result)),
+                        Err(e) => error.set(Some(e)),
+                    }
+                });
+            }
+
+            || {}
+        });
+    }
+
+    if let Some(e) = error.as_ref() {
+        return html! {
+            <section class={classes!("container")}>
+                <h1>{"Screeners"}</h1>
+                <p>{e}</p>
+            </section>
+        };
+    }
+
+    match data.as_ref() {
+        None => {
+            html! {
+                <section class={classes!("container")}>
+                    <h1>{"Screeners"}</h1>
+                    <progress></progress>
+                </section>
+            }
+        }
+        Some(v) => {
+            html! {
+                <section class={classes!("container")}>
+                    <h1>{"Screeners"}</h1>
+                    {v.iter().enumerate().map(|(id, s)| {
+                        let screen = Screener {
+                            id,
+                            name: AttrValue::from(s["name"].as_str().unwrap_or_default().to_string()),
+                            decription: AttrValue::from(s["description"].as_str().unwrap_or_default().to_string()),
+                        };
+
+                        html! { <ScreenCard screen={screen}></ScreenCard> }
+                    }).collect::<Html>()}
+                </section>
+            }
+        }
+    }
+}
 
+#[derive(Properties, PartialEq)]
+pub struct ExportLinksProps {
+    pub url: AttrValue,
+}
+
+// Downloads of the export endpoint at url in both formats
+#[function_component(ExportLinks)]
+pub fn export_links(ExportLinksProps { url }: &ExportLinksProps) -> Html {
     html! {
-        <section class={classes!("container")}>
-            <h1>{"Screeners"}</h1>
-            <ScreenCard screen={screen}></ScreenCard>
-        </section>
+        <p>
+            <a href={format!("{}?format=csv", url)} download="" role="button" class={classes!("secondary", "outline")}>{"CSV"}</a>
+            {" "}
+            <a href={format!("{}?format=xlsx", url)} download="" role="button" class={classes!("secondary", "outline")}>{"Excel"}</a>
+        </p>
+    }
+}
+
+// The body of a successful response, otherwise the message from the server's error envelope
+pub async fn fetch_json<T: DeserializeOwned>(url: &str) -> Result<T, String> {
+    let resp = Request::get(url).send().await.map_err(|e| e.to_string())?;
+
+    if !resp.ok() {
+        return Err(error_message(&resp).await);
+    }
+
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+pub async fn error_message(resp: &Response) -> String {
+    let body: Value = resp.json().await.unwrap_or_default();
+
+    match body["error"]["message"].as_str() {
+        Some(message) => message.to_string(),
+        None => format!(
+            "Error fetching data {} ({})",
+            resp.status(),
+            resp.status_text()
+        ),
     }
 }
 
@@ -157,34 +253,72 @@ pub struct ScreenPageProps {
 #[function_component(ScreenPage)]
 fn screen_page(ScreenPageProps { name }: &ScreenPageProps) -> Html {
     let url = format!("/api/screeners/{}", name);
+    let scores_url = format!("/api/screeners/{}/scores", name);
 
     let data = use_state(|| None);
+    let error = use_state(|| None::<String>);
+    let job = use_state(|| None::<Value>);
+    let found = use_state(Vec::<Value>::new);
 
-    // Request `/api/hello` once
     {
         let data = data.clone();
-        use_effect(move || {
-            if data.is_none() {
+        let error = error.clone();
+        let job = job.clone();
+        let found = found.clone();
+        use_effect_with_deps(
+            move |_| {
                 spawn_local(async move {
-                    let resp = Request::get(&url).send().await.unwrap();
-                    let result: Vec<Value> = {
-                        if !resp.ok() {
-                            Err(format!(
-                                "Error fetching data {} ({})",
-                                resp.status(),
-                                resp.status_text()
-                            ))
-                            .unwrap()
-                        } else {
-                            resp.json().await.map_err(|err| err.to_string()).unwrap()
-                        }
+                    let mut resp = match Request::get(&url).send().await {
+                        Ok(resp) => resp,
+                        Err(e) => return error.set(Some(e.to_string())),
                     };
-                    data.set(Some(result));
+
+                    // The screen has not run yet, it is started in the background and the
+                    // results are requested again once the job completes
+                    if resp.status() == 202 {
+                        let started: Value = resp.json().await.unwrap_or_default();
+                        let id = started["id"].as_u64().unwrap_or_default();
+                        let finished = match watch_job(id, job, found).await {
+                            Ok(finished) => finished,
+                            Err(e) => return error.set(Some(e)),
+                        };
+
+                        if finished["status"] != "completed" {
+                            return;
+                        }
+
+                        resp = match Request::get(&url).send().await {
+                            Ok(resp) => resp,
+                            Err(e) => return error.set(Some(e.to_string())),
+                        };
+                    }
+
+                    if !resp.ok() {
+                        error.set(Some(error_message(&resp).await));
+                        return;
+                    }
+
+                    let result: Vec<Value> = resp.json().await.unwrap_or_default();
+
+                    // Ranking screens also have per-factor scores for the stocks that made the cut
+                    let scores: Vec<Value> = fetch_json(&scores_url).await.unwrap_or_default();
+
+                    data.set(Some((result, scores)));
                 });
-            }
 
-            || {}
-        });
+                || {}
+            },
+            name.clone(),
+        );
+    }
+
+    if let Some(e) = error.as_ref() {
+        return html! {
+            <section class={classes!("container")}>
+                <h1>{name}</h1>
+                <p>{e}</p>
+            </section>
+        };
     }
 
     match data.as_ref() {
@@ -192,14 +326,33 @@ fn screen_page(ScreenPageProps { name }: &ScreenPageProps) -> Html {
             html! {
                 <section class={classes!("container")}>
                     <h1>{name}</h1>
-                    <progress></progress>
+                    {
+                        match job.as_ref() {
+                            Some(job) => html! { <JobProgress job={job.clone()} /> },
+                            None => html! { <progress></progress> },
+                        }
+                    }
+                    if !found.is_empty() {
+                        <LiveResults stocks={(*found).clone()} />
+                    }
                 </section>
             }
         }
-        Some(v) => {
+        Some((_, scores)) if !scores.is_empty() => {
             html! {
                 <section class={classes!("container")}>
                     <h1>{name}</h1>
+                    <ExportLinks url={format!("/api/screeners/{}/export", name)} />
+                    <RankingTable scores={scores.clone()}></RankingTable>
+                    <RunHistory name={name.clone()} />
+                </section>
+            }
+        }
+        Some((v, _)) => {
+            html! {
+                <section class={classes!("container")}>
+                    <h1>{name}</h1>
+                    <ExportLinks url={format!("/api/screeners/{}/export", name)} />
                     <table role="grid">
                         <thead>
                             <tr>
@@ -214,12 +367,75 @@ fn screen_page(ScreenPageProps { name }: &ScreenPageProps) -> Html {
                             }).collect::<Html>()}
                         </tbody>
                     </table>
+                    <RunHistory name={name.clone()} />
                 </section>
             }
         }
     }
 }
 
+// Follows the job's event stream, showing each stock as soon as it passes, and falls back to
+// polling if the stream cannot be opened or drops before the job finishes
+async fn watch_job(
+    id: u64,
+    job: UseStateHandle<Option<Value>>,
+    found: UseStateHandle<Vec<Value>>,
+) -> Result<Value, String> {
+    if let Ok(mut events) = EventSource::new(&format!("/api/jobs/{}/events", id)) {
+        if let Ok(mut messages) = events.subscribe("message") {
+            let mut stocks: Vec<Value> = vec![];
+
+            while let Some(Ok((_, message))) = messages.next().await {
+                let event: Value = message
+                    .data()
+                    .as_string()
+                    .and_then(|data| serde_json::from_str(&data).ok())
+                    .unwrap_or_default();
+
+                match event["type"].as_str() {
+                    // A reconnected stream replays the stocks that passed before it dropped
+                    Some("passed") => {
+                        let stock = event["stock"].clone();
+                        let seen = stocks.iter().any(|s| s["symbol"] == stock["symbol"]);
+
+                        if !seen {
+                            stocks.push(stock);
+                            found.set(stocks.clone());
+                        }
+                    }
+                    Some("progress") => job.set(Some(event["job"].clone())),
+                    Some("finished") => {
+                        events.close();
+                        job.set(Some(event["job"].clone()));
+                        return Ok(event["job"].clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    wait_for_job(id, job).await
+}
+
+// Polls the job every second until it is no longer running, returning its final state, or the
+// error of the first poll that fails
+async fn wait_for_job(id: u64, job: UseStateHandle<Option<Value>>) -> Result<Value, String> {
+    let url = format!("/api/jobs/{}", id);
+
+    loop {
+        let current: Value = fetch_json(&url).await?;
+
+        job.set(Some(current.clone()));
+
+        if current["status"] != "running" {
+            return Ok(current);
+        }
+
+        TimeoutFuture::new(1_000).await;
+    }
+}
+
 // fn on_run(name: String) -> Vec<Value> {
 //     let res : Vec<Value> = Request::get("/api/screeners/")
 // }
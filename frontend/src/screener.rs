@@ -1,3 +1,6 @@
+use gloo_net::http::Request;
+use serde_json::Value;
+use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 
 #[derive(Clone, PartialEq)]
@@ -25,3 +28,241 @@ pub fn screen_card(ScreenCardProps { screen }: &ScreenCardProps) -> Html {
         </article>
     }
 }
+
+#[derive(Properties, PartialEq)]
+pub struct RankingTableProps {
+    pub scores: Vec<Value>,
+}
+
+#[function_component(RankingTable)]
+pub fn ranking_table(RankingTableProps { scores }: &RankingTableProps) -> Html {
+    let factors: Vec<String> = scores[0]["factors"]
+        .as_array()
+        .map(|factors| {
+            factors
+                .iter()
+                .map(|f| f["name"].as_str().unwrap_or_default().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    html! {
+        <table role="grid">
+            <thead>
+                <tr>
+                    <th scope="col"><strong>{"Rank"}</strong></th>
+                    <th scope="col"><strong>{"Symbol"}</strong></th>
+                    <th scope="col"><strong>{"Score"}</strong></th>
+                    {factors.iter().map(|name| html! {
+                        <th scope="col"><strong>{name}</strong></th>
+                    }).collect::<Html>()}
+                </tr>
+            </thead>
+            <tbody>
+                {scores.iter().map(|s| html! {
+                    <tr>
+                        <td>{s["rank"].as_u64().unwrap_or_default()}</td>
+                        <td><a href={format!("/stock/{}", s["symbol"].as_str().unwrap())}>{s["symbol"].as_str()}</a></td>
+                        <td>{format!("{:.2}", s["score"].as_f64().unwrap_or_default() * 100.0)}</td>
+                        {s["factors"].as_array().unwrap().iter().map(|f| html! {
+                            <td>
+                                {format!("{:.4}", f["value"].as_f64().unwrap_or_default())}
+                                <small>{format!(" (#{})", f["rank"].as_u64().unwrap_or_default())}</small>
+                            </td>
+                        }).collect::<Html>()}
+                    </tr>
+                }).collect::<Html>()}
+            </tbody>
+        </table>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct JobProgressProps {
+    pub job: Value,
+}
+
+#[function_component(JobProgress)]
+pub fn job_progress(JobProgressProps { job }: &JobProgressProps) -> Html {
+    let id = job["id"].as_u64().unwrap_or_default();
+    let total = job["total"].as_u64().unwrap_or_default();
+    let completed = job["completed"].as_u64().unwrap_or_default();
+    let status = job["status"].as_str().unwrap_or_default();
+
+    let on_cancel = Callback::from(move |_: MouseEvent| {
+        spawn_local(async move {
+            let _ = Request::post(&format!("/api/jobs/{}/cancel", id))
+                .send()
+                .await;
+        });
+    });
+
+    html! {
+        <article>
+            if total == 0 {
+                <progress></progress>
+            } else {
+                <progress value={completed.to_string()} max={total.to_string()}></progress>
+            }
+            <p>
+                {format!(
+                    "{} of {} stocks screened, {} passed so far",
+                    completed,
+                    total,
+                    job["passed"].as_u64().unwrap_or_default()
+                )}
+            </p>
+            {
+                match status {
+                    "running" => html! { <button class="secondary" onclick={on_cancel}>{"Cancel"}</button> },
+                    "cancelled" => html! { <p><mark>{"The screen was cancelled."}</mark></p> },
+                    "failed" => html! { <p><mark>{format!("The screen failed: {}", job["error"].as_str().unwrap_or_default())}</mark></p> },
+                    _ => html! {},
+                }
+            }
+        </article>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct LiveResultsProps {
+    pub stocks: Vec<Value>,
+}
+
+// Stocks that passed while the screen is still running, in the order they were found
+#[function_component(LiveResults)]
+pub fn live_results(LiveResultsProps { stocks }: &LiveResultsProps) -> Html {
+    let metrics: Vec<String> = stocks[0]["metrics"]
+        .as_array()
+        .map(|metrics| {
+            metrics
+                .iter()
+                .map(|m| m["name"].as_str().unwrap_or_default().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    html! {
+        <figure>
+            <table role="grid">
+                <thead>
+                    <tr>
+                        <th scope="col"><strong>{"Symbol"}</strong></th>
+                        {metrics.iter().map(|name| html! {
+                            <th scope="col"><small>{name}</small></th>
+                        }).collect::<Html>()}
+                    </tr>
+                </thead>
+                <tbody>
+                    {stocks.iter().map(|s| html! {
+                        <tr>
+                            <td><a href={format!("/stock/{}", s["symbol"].as_str().unwrap_or_default())}>{s["symbol"].as_str()}</a></td>
+                            {s["metrics"].as_array().cloned().unwrap_or_default().iter().map(|m| html! {
+                                <td>{
+                                    match m["value"].as_f64() {
+                                        Some(v) => format!("{:.4}", v),
+                                        None => String::from("N/A"),
+                                    }
+                                }</td>
+                            }).collect::<Html>()}
+                        </tr>
+                    }).collect::<Html>()}
+                </tbody>
+            </table>
+        </figure>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RunHistoryProps {
+    pub name: AttrValue,
+}
+
+// Past runs of the screen and which stocks entered or left it in the latest one
+#[function_component(RunHistory)]
+pub fn run_history(RunHistoryProps { name }: &RunHistoryProps) -> Html {
+    let runs_url = format!("/api/screeners/{}/runs", name);
+    let diff_url = format!("/api/screeners/{}/runs/diff", name);
+
+    let data = use_state(|| None);
+
+    {
+        let data = data.clone();
+        use_effect_with_deps(
+            move |_| {
+                spawn_local(async move {
+                    let runs: Vec<Value> = match Request::get(&runs_url).send().await {
+                        Ok(resp) if resp.ok() => resp.json().await.unwrap_or_default(),
+                        _ => vec![],
+                    };
+
+                    // Only exists once the screen has run at least twice
+                    let diff: Value = match Request::get(&diff_url).send().await {
+                        Ok(resp) if resp.ok() => resp.json().await.unwrap_or_default(),
+                        _ => Value::Null,
+                    };
+
+                    data.set(Some((runs, diff)));
+                });
+
+                || {}
+            },
+            name.clone(),
+        );
+    }
+
+    match data.as_ref() {
+        Some((runs, diff)) if !runs.is_empty() => html! {
+            <details>
+                <summary>{format!("Run History ({})", runs.len())}</summary>
+                if !diff.is_null() {
+                    <p>
+                        <u>{"Entered:"}</u>{" "}
+                        {symbol_list(&diff["entered"])}
+                    </p>
+                    <p>
+                        <u>{"Exited:"}</u>{" "}
+                        {symbol_list(&diff["exited"])}
+                    </p>
+                }
+                <table role="grid">
+                    <thead>
+                        <tr>
+                            <th scope="col"><strong>{"Run"}</strong></th>
+                            <th scope="col"><strong>{"Finished"}</strong></th>
+                            <th scope="col"><strong>{"Data As Of"}</strong></th>
+                            <th scope="col"><strong>{"Passed"}</strong></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {runs.iter().rev().map(|run| html! {
+                            <tr>
+                                <td>{run["id"].as_u64().unwrap_or_default()}</td>
+                                <td>{run["finished_at"].as_str().unwrap_or_default().get(..16)}</td>
+                                <td>{run["data_as_of"].as_str().unwrap_or("N/A")}</td>
+                                <td>{run["passed"].as_u64().unwrap_or_default()}</td>
+                            </tr>
+                        }).collect::<Html>()}
+                    </tbody>
+                </table>
+            </details>
+        },
+        _ => html! {},
+    }
+}
+
+fn symbol_list(symbols: &Value) -> Html {
+    let symbols = symbols.as_array().cloned().unwrap_or_default();
+
+    if symbols.is_empty() {
+        return html! { {"None"} };
+    }
+
+    symbols
+        .iter()
+        .map(|s| {
+            let symbol = s.as_str().unwrap_or_default().to_string();
+            html! { <><a href={format!("/stock/{}", symbol)}>{symbol.clone()}</a>{" "}</> }
+        })
+        .collect::<Html>()
+}
@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo_timers::future::TimeoutFuture;
+use serde_json::Value;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::fetch_json;
+
+// Waits for typing to pause before asking the server
+const DEBOUNCE_MS: u32 = 200;
+
+#[function_component(SymbolSearch)]
+pub fn symbol_search() -> Html {
+    let suggestions = use_state(Vec::<Value>::new);
+    let latest = use_mut_ref(String::new);
+
+    let on_input = {
+        let suggestions = suggestions.clone();
+        let latest = latest.clone();
+
+        Callback::from(move |e: InputEvent| {
+            let Some(input) = e
+                .target()
+                .and_then(|t| t.dyn_into::<HtmlInputElement>().ok())
+            else {
+                return;
+            };
+
+            let query = input.value().trim().to_string();
+            *latest.borrow_mut() = query.clone();
+
+            if query.is_empty() {
+                suggestions.set(vec![]);
+                return;
+            }
+
+            let suggestions = suggestions.clone();
+            let latest = latest.clone();
+            spawn_local(async move {
+                TimeoutFuture::new(DEBOUNCE_MS).await;
+                if !is_latest(&latest, &query) {
+                    return;
+                }
+
+                let url = format!("/api/search?q={}&limit=8", encode(&query));
+                let result = fetch_json::<Vec<Value>>(&url).await.unwrap_or_default();
+
+                // An older request finishing late must not replace newer suggestions
+                if is_latest(&latest, &query) {
+                    suggestions.set(result);
+                }
+            });
+        })
+    };
+
+    // Enter opens the best match, or the typed text as a ticker when nothing matched
+    let on_keydown = {
+        let suggestions = suggestions.clone();
+        let latest = latest.clone();
+
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() != "Enter" {
+                return;
+            }
+
+            let symbol = suggestions
+                .first()
+                .and_then(|s| s["symbol"].as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| latest.borrow().to_uppercase());
+
+            if !symbol.is_empty() {
+                open_stock(&symbol);
+            }
+        })
+    };
+
+    html! {
+        <div class={classes!("symbol-search")}>
+            <input type="search" id="search" name="search" placeholder="Search Symbols" autocomplete="off"
+                oninput={on_input} onkeydown={on_keydown} />
+            if !suggestions.is_empty() {
+                <ul class={classes!("suggestions")}>
+                    {suggestions.iter().map(|s| {
+                        let symbol = s["symbol"].as_str().unwrap_or_default();
+
+                        html! {
+                            <li>
+                                <a href={format!("/stock/{}", symbol)}>
+                                    <strong>{symbol}</strong>{" "}
+                                    <small>{s["name"].as_str().unwrap_or_default()}</small>
+                                </a>
+                            </li>
+                        }
+                    }).collect::<Html>()}
+                </ul>
+            }
+        </div>
+    }
+}
+
+fn is_latest(latest: &Rc<RefCell<String>>, query: &str) -> bool {
+    *latest.borrow() == query
+}
+
+fn open_stock(symbol: &str) {
+    if let Some(window) = web_sys::window() {
+        let _ = window.location().set_href(&format!("/stock/{}", symbol));
+    }
+}
+
+// Company names contain spaces and ampersands
+fn encode(query: &str) -> String {
+    query
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -3,11 +3,13 @@ use std::vec;
 use wasm_bindgen::JsCast;
 use web_sys::{Event, EventTarget, HtmlSelectElement};
 
-use gloo_net::http::Request;
 use serde_json::Value;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 
+use crate::dividends::Dividends;
+use crate::{fetch_json, ExportLinks};
+
 struct StatementData {
     pub name: String,
     pub field_data: Vec<TableField>,
@@ -31,6 +33,7 @@ pub fn stock(StockProps { symbol }: &StockProps) -> Html {
     let url = format!("/api/stock/{}", symbol);
 
     let data = use_state(|| None);
+    let error = use_state(|| None::<String>);
     let statement_data = use_state(|| get_income_statement_meta());
 
     let on_change = {
@@ -58,23 +61,14 @@ pub fn stock(StockProps { symbol }: &StockProps) -> Html {
 
     {
         let data = data.clone();
+        let error = error.clone();
         use_effect(move || {
-            if data.is_none() {
+            if data.is_none() && error.is_none() {
                 spawn_local(async move {
-                    let resp = Request::get(&url).send().await.unwrap();
-                    let result: Vec<Value> = {
-                        if !resp.ok() {
-                            Err(format!(
-                                "Error fetching data {} ({})",
-                                resp.status(),
-                                resp.status_text()
-                            ))
-                            .unwrap()
-                        } else {
-                            resp.json().await.map_err(|err| err.to_string()).unwrap()
-                        }
-                    };
-                    data.set(Some(result));
+                    match fetch_json::<Vec<Value>>(&url).await {
+                        Ok(result) => data.set(Some(result)),
+                        Err(e) => error.set(Some(e)),
+                    }
                 });
             }
 
@@ -82,6 +76,15 @@ pub fn stock(StockProps { symbol }: &StockProps) -> Html {
         });
     }
 
+    if let Some(e) = error.as_ref() {
+        return html! {
+            <section class={classes!("container")}>
+                <h1>{symbol}</h1>
+                <p>{e}</p>
+            </section>
+        };
+    }
+
     match data.as_ref() {
         None => {
             html! {
@@ -157,6 +160,7 @@ pub fn stock(StockProps { symbol }: &StockProps) -> Html {
                                 <option value="cashflow">{"Cash Flow Statement"}</option>
                             </select>
                             <p><small>{" * Financials in "}<strong>{"Millions"}</strong>{" of "}<strong>{last_income_quarter["reportedCurrency"].as_str()}</strong></small></p>
+                            <ExportLinks url={format!("/api/stock/{}/export", symbol)} />
                         </section>
                     </section>
                     <section>
@@ -228,6 +232,9 @@ pub fn stock(StockProps { symbol }: &StockProps) -> Html {
                                 </tbody>
                             </table>
                     </section>
+                    <section class={classes!("container")}>
+                        <Dividends symbol={symbol.clone()} />
+                    </section>
                 </>
             }
         }
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::helper_structs::{HistoricalPrice, TimePeriod};
+use crate::jobs::JobHandle;
+use crate::prices;
+use crate::rules::{Evaluation, ScreenDefinition, Source};
+use crate::screener::{self, Screener};
+use crate::stock::{Stock, REPORTING_LAG_DAYS};
+use crate::BACKTESTS;
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct BacktestRequest {
+    pub screen: String,
+    pub start: NaiveDate,
+    // Today when not set
+    #[serde(default)]
+    pub end: Option<NaiveDate>,
+    #[serde(default = "default_rebalance_months")]
+    pub rebalance_months: u32,
+    #[serde(default = "default_benchmark")]
+    pub benchmark: String,
+    // Holds only the first stocks in the screen's order, otherwise every stock that passed
+    #[serde(default)]
+    pub top_n: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Backtest {
+    pub id: usize,
+    pub screen: String,
+    pub definition: ScreenDefinition,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub rebalance_months: u32,
+    pub benchmark: String,
+    pub top_n: Option<usize>,
+    pub periods: Vec<BacktestPeriod>,
+    pub total_return: f64,
+    pub benchmark_total_return: f64,
+    pub cagr: Option<f64>,
+    pub benchmark_cagr: Option<f64>,
+    // Ways the result is more optimistic or less exact than it looks
+    pub warnings: Vec<String>,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct BacktestPeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    // The basket picked at the start of the period, in the screen's order
+    pub symbols: Vec<String>,
+    // Stocks in the basket with a price at both ends of the period
+    pub priced: usize,
+    // Equal weighted, None when nothing was held and the period counts as cash
+    pub basket_return: Option<f64>,
+    pub benchmark_return: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct BacktestSummary {
+    pub id: usize,
+    pub screen: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub benchmark: String,
+    pub total_return: f64,
+    pub benchmark_total_return: f64,
+    pub finished_at: DateTime<Utc>,
+}
+
+fn default_rebalance_months() -> u32 {
+    12
+}
+
+fn default_benchmark() -> String {
+    "SPY".to_string()
+}
+
+impl BacktestRequest {
+    pub fn end(&self) -> NaiveDate {
+        self.end.unwrap_or_else(|| Utc::now().date_naive())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.start >= self.end() {
+            return Err("start has to be before end".to_string());
+        }
+
+        if self.end() > Utc::now().date_naive() {
+            return Err("end can not be in the future".to_string());
+        }
+
+        if self.rebalance_months == 0 {
+            return Err("rebalance_months has to be at least 1".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+// TTM ratios have no quarterly counterpart the backtest rebuilds, every criterion reading them
+// would fail at every date and leave the basket empty
+pub fn check_screen(screen: &ScreenDefinition) -> Result<(), String> {
+    let rebuilt = [Source::Income, Source::KeyMetrics];
+    let unsupported = screen
+        .fields()
+        .into_iter()
+        .find(|f| f.period == TimePeriod::TTM() && !rebuilt.contains(&f.source));
+
+    match unsupported {
+        Some(field) => Err(format!(
+            "{} can not be backtested, {} has no past values, use its quarterly version instead",
+            screen.name, field
+        )),
+        None => Ok(()),
+    }
+}
+
+impl From<&Backtest> for BacktestSummary {
+    fn from(backtest: &Backtest) -> Self {
+        Self {
+            id: backtest.id,
+            screen: backtest.screen.clone(),
+            start: backtest.start,
+            end: backtest.end,
+            benchmark: backtest.benchmark.clone(),
+            total_return: backtest.total_return,
+            benchmark_total_return: backtest.benchmark_total_return,
+            finished_at: backtest.finished_at,
+        }
+    }
+}
+
+// Runs the screen at every rebalance date against only what had been published by then, holds
+// the stocks that passed until the next date and compares the basket with the benchmark
+pub async fn run(
+    screen: &ScreenDefinition,
+    request: &BacktestRequest,
+    job: &JobHandle,
+) -> Result<Backtest, String> {
+    let end = request.end();
+    let dates = rebalance_dates(request.start, end, request.rebalance_months);
+
+    let mut scr = Screener::new();
+    scr.init_screen(&screen.universe).await;
+
+    if scr.stocks_to_screen.is_empty() {
+        return Err(
+            "no stocks in the screen's universe, the symbol list could not be loaded".to_string(),
+        );
+    }
+
+    job.set_total(scr.stocks_to_screen.len()).await;
+
+    let years = (Utc::now().year() - request.start.year() + 1).clamp(1, 50) as u8;
+    let stocks = screener::load_history(
+        &scr.stocks_to_screen,
+        history_fields(screen, years),
+        scr.concurrency,
+        job,
+    )
+    .await;
+
+    if job.is_cancelled() {
+        return Err("cancelled".to_string());
+    }
+
+    let mut baskets = vec![];
+
+    for window in dates.windows(2) {
+        let mut evaluations = vec![];
+        let mut past = vec![];
+
+        for stock in &stocks {
+            let then = stock.as_of(window[0]);
+            evaluations.push(screen.evaluate_offline(&then));
+            past.push(then);
+        }
+
+        baskets.push(basket(screen, &evaluations, &past, request.top_n));
+    }
+
+    let mut symbols: Vec<&String> = baskets.iter().flatten().collect();
+    symbols.push(&request.benchmark);
+    symbols.sort();
+    symbols.dedup();
+
+    job.set_total(stocks.len() + symbols.len()).await;
+
+    let mut histories = HashMap::new();
+
+    for symbol in symbols {
+        if job.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+
+        histories.insert(symbol.clone(), prices::history(symbol).await);
+        job.advance().await;
+    }
+
+    let periods: Vec<BacktestPeriod> = dates
+        .windows(2)
+        .zip(baskets)
+        .map(|(window, symbols)| {
+            let returns: Vec<f64> = symbols
+                .iter()
+                .filter_map(|s| period_return(histories.get(s), window[0], window[1]))
+                .collect();
+
+            BacktestPeriod {
+                start: window[0],
+                end: window[1],
+                priced: returns.len(),
+                basket_return: mean(&returns),
+                benchmark_return: period_return(
+                    histories.get(&request.benchmark),
+                    window[0],
+                    window[1],
+                ),
+                symbols,
+            }
+        })
+        .collect();
+
+    let total_return = compound(periods.iter().map(|p| p.basket_return));
+    let benchmark_total_return = compound(periods.iter().map(|p| p.benchmark_return));
+
+    Ok(Backtest {
+        id: 0,
+        screen: screen.name.clone(),
+        definition: screen.clone(),
+        start: request.start,
+        end,
+        rebalance_months: request.rebalance_months,
+        benchmark: request.benchmark.clone(),
+        top_n: request.top_n,
+        total_return,
+        benchmark_total_return,
+        cagr: annualized(total_return, request.start, end),
+        benchmark_cagr: annualized(benchmark_total_return, request.start, end),
+        warnings: warnings(screen, &periods),
+        periods,
+        finished_at: Utc::now(),
+    })
+}
+
+pub async fn record(mut backtest: Backtest) {
+    let mut backtests = BACKTESTS.lock().await;
+
+    backtest.id = backtests.len() + 1;
+    backtests.push(backtest);
+}
+
+pub async fn list() -> Vec<BacktestSummary> {
+    BACKTESTS
+        .lock()
+        .await
+        .iter()
+        .map(BacktestSummary::from)
+        .collect()
+}
+
+pub async fn find_backtest(id: usize) -> Option<Backtest> {
+    BACKTESTS.lock().await.iter().find(|b| b.id == id).cloned()
+}
+
+// Every rebalance date from the start, with the end closing the last period
+fn rebalance_dates(start: NaiveDate, end: NaiveDate, months: u32) -> Vec<NaiveDate> {
+    let mut dates = vec![];
+    let mut date = start;
+
+    while date < end {
+        dates.push(date);
+
+        date = match date.checked_add_months(Months::new(months)) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    dates.push(end);
+    dates
+}
+
+// Enough extra periods for the screen to see as far back at the start as it does today. TTM
+// figures are rebuilt from four quarters at each date, the DCF only exists for today.
+fn history_fields(screen: &ScreenDefinition, years: u8) -> Vec<(Source, TimePeriod)> {
+    let mut fields = vec![];
+    let quarters = |n: u8| TimePeriod::Quarter(n.saturating_add(years.saturating_mul(4)));
+
+    for field in screen.fields() {
+        let period = match field.period {
+            TimePeriod::Annual(n) => TimePeriod::Annual(n.saturating_add(years)),
+            TimePeriod::Quarter(n) => quarters(n),
+            TimePeriod::TTM() => quarters(4),
+            TimePeriod::NA() => TimePeriod::NA(),
+        };
+
+        if field.source == Source::Dcf || fields.contains(&(field.source, period.clone())) {
+            continue;
+        }
+
+        fields.push((field.source, period));
+    }
+
+    if screen.universe.needs_profile() && !fields.iter().any(|(s, _)| *s == Source::Profile) {
+        fields.push((Source::Profile, TimePeriod::NA()));
+    }
+
+    fields
+}
+
+// Ranking screens hold their ranked stocks, others the passing stocks in sort order
+fn basket(
+    screen: &ScreenDefinition,
+    evaluations: &[Evaluation],
+    stocks: &[Stock],
+    top_n: Option<usize>,
+) -> Vec<String> {
+    let mut symbols: Vec<String> = match (&screen.ranking, &screen.sort) {
+        (Some(ranking), _) => ranking
+            .rank(evaluations)
+            .into_iter()
+            .map(|r| r.symbol)
+            .collect(),
+        (None, sort) => {
+            let mut passed: Vec<(String, Option<f64>)> = evaluations
+                .iter()
+                .zip(stocks)
+                .filter(|(e, _)| e.passed)
+                .map(|(e, stock)| {
+                    let key = sort.as_ref().and_then(|sort| sort.by.evaluate(stock));
+                    (e.symbol.clone(), key)
+                })
+                .collect();
+
+            if let Some(sort) = sort {
+                passed.sort_by(|(_, a), (_, b)| sort.order(*a, *b));
+            }
+
+            passed.into_iter().map(|(symbol, _)| symbol).collect()
+        }
+    };
+
+    if let Some(n) = top_n {
+        symbols.truncate(n);
+    }
+
+    symbols
+}
+
+fn period_return(
+    prices: Option<&Vec<HistoricalPrice>>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Option<f64> {
+    let prices = prices?;
+    let start = prices::close_on(prices, start).filter(|p| *p > 0.0)?;
+    let end = prices::close_on(prices, end)?;
+
+    Some(end / start - 1.0)
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+// Periods without a return count as holding cash
+fn compound(returns: impl Iterator<Item = Option<f64>>) -> f64 {
+    returns.fold(1.0, |total, r| total * (1.0 + r.unwrap_or_default())) - 1.0
+}
+
+fn annualized(total_return: f64, start: NaiveDate, end: NaiveDate) -> Option<f64> {
+    let years = (end - start).num_days() as f64 / 365.25;
+
+    if years <= 0.0 || total_return <= -1.0 {
+        return None;
+    }
+
+    Some((1.0 + total_return).powf(1.0 / years) - 1.0)
+}
+
+fn warnings(screen: &ScreenDefinition, periods: &[BacktestPeriod]) -> Vec<String> {
+    let fields = screen.fields();
+    let uses = |source: Source| fields.iter().any(|f| f.source == source);
+
+    let mut warnings = vec![
+        "the universe is today's listed symbols, companies delisted since the start are missing"
+            .to_string(),
+    ];
+
+    if uses(Source::Ratios) || uses(Source::KeyMetrics) {
+        warnings.push(format!(
+            "ratios and key metrics have no filing date, they are assumed public {} days after the period ended",
+            REPORTING_LAG_DAYS
+        ));
+    }
+
+    if fields.iter().any(|f| f.period == TimePeriod::TTM()) {
+        warnings.push(
+            "TTM fields are rebuilt from the four quarters published by each date, key metric \
+             yields add up each quarter's own and multiples are taken over the summed yields"
+                .to_string(),
+        );
+    }
+
+    if uses(Source::Dcf) {
+        warnings.push("the DCF only exists for today and has no value in the past".to_string());
+    }
+
+    if uses(Source::Profile) || screen.universe.needs_profile() {
+        warnings.push(
+            "company profiles (sector, market cap, ...) are today's, not as of each date"
+                .to_string(),
+        );
+    }
+
+    let cash = periods.iter().filter(|p| p.basket_return.is_none()).count();
+    if cash > 0 {
+        warnings.push(format!(
+            "{} of {} periods held nothing with prices and count as cash",
+            cash,
+            periods.len()
+        ));
+    }
+
+    let missing = periods
+        .iter()
+        .filter(|p| p.benchmark_return.is_none())
+        .count();
+    if missing > 0 {
+        warnings.push(format!(
+            "the benchmark has no prices for {} of {} periods",
+            missing,
+            periods.len()
+        ));
+    }
+
+    warnings
+}
@@ -0,0 +1,202 @@
+use std::sync::atomic::Ordering;
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::task::JoinSet;
+use utoipa::ToSchema;
+
+use crate::cache;
+use crate::errors::{validate_symbol, ApiError};
+use crate::helper_structs::TimePeriod;
+use crate::rules::Source;
+use crate::CONCURRENCY;
+
+// Enough for a dashboard, a whole exchange is what the screens and /api/index are for
+pub const MAX_SYMBOLS: usize = 200;
+
+// What /api/stock/:name returns
+const DEFAULT_SECTIONS: [Source; 7] = [
+    Source::Income,
+    Source::Balance,
+    Source::CashFlow,
+    Source::Ratios,
+    Source::KeyMetrics,
+    Source::Profile,
+    Source::Dcf,
+];
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct BatchRequest {
+    pub symbols: Vec<String>,
+    // Everything /api/stock/:name returns when not given
+    #[serde(default)]
+    pub sections: Vec<Source>,
+    // annual (the default), quarter or ttm
+    pub period: Option<String>,
+    // How many periods, 10 when not given
+    pub limit: Option<u8>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BatchResult {
+    pub symbol: String,
+    // The status /api/stock/:name would have answered with for this symbol
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+    // Rows of each requested section, keyed by its name, newest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<Map<String, Value>>,
+}
+
+impl BatchRequest {
+    // Upper cased, in the order asked for and without repeats
+    pub fn symbols(&self) -> Result<Vec<String>, String> {
+        let mut symbols: Vec<String> = vec![];
+
+        for symbol in &self.symbols {
+            let symbol = symbol.trim().to_uppercase();
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+
+        match symbols.len() {
+            0 => Err("symbols has to list at least one ticker".to_string()),
+            n if n > MAX_SYMBOLS => Err(format!(
+                "at most {} symbols fit in one batch, got {}",
+                MAX_SYMBOLS, n
+            )),
+            _ => Ok(symbols),
+        }
+    }
+
+    pub fn sections(&self) -> Vec<Source> {
+        if self.sections.is_empty() {
+            return DEFAULT_SECTIONS.to_vec();
+        }
+
+        let mut sections = vec![];
+        for section in &self.sections {
+            if !sections.contains(section) {
+                sections.push(*section);
+            }
+        }
+
+        sections
+    }
+}
+
+// Fetches the stocks as many at a time as the screens do, the shared rate limiter spaces out
+// the requests. One failing symbol is reported in its result and does not fail the batch.
+pub async fn fetch(
+    symbols: Vec<String>,
+    sections: Vec<Source>,
+    period: TimePeriod,
+) -> Vec<BatchResult> {
+    let concurrency = CONCURRENCY.load(Ordering::Relaxed).max(1);
+    let mut finished: Vec<Option<BatchResult>> = symbols.iter().map(|_| None).collect();
+    let mut pending = symbols.clone().into_iter().enumerate();
+    let mut tasks = JoinSet::new();
+
+    loop {
+        while tasks.len() < concurrency {
+            match pending.next() {
+                Some((position, symbol)) => {
+                    let sections = sections.clone();
+                    let period = period.clone();
+
+                    tasks.spawn(async move {
+                        let result = match fetch_one(&symbol, &sections, period).await {
+                            Ok(data) => BatchResult {
+                                symbol,
+                                status: 200,
+                                error: None,
+                                data: Some(data),
+                            },
+                            Err(error) => BatchResult {
+                                symbol,
+                                status: error.status.as_u16(),
+                                error: Some(error),
+                                data: None,
+                            },
+                        };
+
+                        (position, result)
+                    });
+                }
+                None => break,
+            }
+        }
+
+        match tasks.join_next().await {
+            Some(Ok((position, result))) => finished[position] = Some(result),
+            Some(Err(e)) => tracing::error!(error = %e, "fetching a stock for a batch failed"),
+            None => break,
+        }
+    }
+
+    // In the order the symbols were asked for, a task that panicked still answers for its symbol
+    finished
+        .into_iter()
+        .zip(symbols)
+        .map(|(result, symbol)| {
+            result.unwrap_or_else(|| {
+                let error = ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    format!("Fetching {symbol} failed unexpectedly"),
+                );
+
+                BatchResult {
+                    symbol,
+                    status: error.status.as_u16(),
+                    error: Some(error),
+                    data: None,
+                }
+            })
+        })
+        .collect()
+}
+
+async fn fetch_one(
+    symbol: &str,
+    sections: &[Source],
+    period: TimePeriod,
+) -> Result<Map<String, Value>, ApiError> {
+    let symbol = validate_symbol(symbol)?;
+    // Network calls are made on a copy so the other tasks can use the cache meanwhile
+    let mut stock = cache::get_known_stock(symbol).await?;
+    let mut data = Map::new();
+
+    for section in sections {
+        // There are no TTM balance sheets or cash flows to fetch
+        if period == TimePeriod::TTM() && matches!(section, Source::Balance | Source::CashFlow) {
+            data.insert(section_name(*section), Value::Array(vec![]));
+            continue;
+        }
+
+        if let Err(error) = stock.fetch_source(*section, period.clone()).await {
+            cache::store_stock(stock).await;
+            return Err(error);
+        }
+
+        data.insert(
+            section_name(*section),
+            Value::Array(stock.rows(*section, &period)),
+        );
+    }
+
+    cache::store_stock(stock).await;
+
+    Ok(data)
+}
+
+fn section_name(section: Source) -> String {
+    serde_json::to_value(section)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
@@ -1,32 +1,65 @@
 use crate::{metrics::Metrics, statements::Statements, stock::Stock, CACHE, other::Other};
+use crate::errors::ApiError;
+use crate::helper_functions::api_result;
+use crate::helper_structs::{Profile, TimePeriod};
 use tokio::sync::{MutexGuard, MappedMutexGuard};
 use std::{fs, ops::Deref};
 
 // Make struct and pass it from main into the needed areas
 
 pub async fn get_or_add_stock(symbol: String) -> MappedMutexGuard<'static, Stock> {
-    let stock_exists = stock_index_in_cache(symbol.to_owned());
-
-    match stock_exists.await {
-        Some(v) => MutexGuard::map(CACHE.lock().await, |d| d.get_mut(v).unwrap()),
-        None => add_stock(Stock {
-            cache_index: None,
+    // Looked up and added under one lock so two tasks can not add the same ticker twice
+    let mut cache = CACHE.lock().await;
+    let index = cache.iter().position(|stock| stock.ticker == symbol).unwrap_or_else(|| {
+        let index = cache.len();
+        cache.push(Stock {
+            cache_index: Some(index),
             ticker: symbol,
             statements: Statements::new(),
             metrics: Metrics::new(),
             other: Other::new(),
-        }).await,
-    }
+        });
+        index
+    });
+
+    MutexGuard::map(cache, |d| d.get_mut(index).unwrap())
 }
 
-pub async fn add_stock(mut stock: Stock) -> MappedMutexGuard<'static, Stock> {
-    let length = CACHE.lock().await.len();
+// Unknown tickers are turned away before they take up a place in the cache. A stock counts as
+// known once the data provider has a profile for it. Handlers get a copy to fetch into, which
+// goes back with store_stock, so the cache is never locked while waiting on the provider.
+pub async fn get_known_stock(symbol: String) -> Result<Stock, ApiError> {
+    if let Some(index) = stock_index_in_cache(symbol.to_owned()).await {
+        let cache = CACHE.lock().await;
+        if !cache[index].other.profile.is_empty() {
+            return Ok(cache[index].clone());
+        }
+    }
 
-    stock.set_cache_index(Some(length));
+    let profile = api_result::<Profile>(&TimePeriod::NA(), &symbol, "".to_string()).await?;
 
-    CACHE.lock().await.push(stock);
+    if profile.is_empty() {
+        return Err(ApiError::unknown_symbol(&symbol));
+    }
+
+    let mut stock = get_or_add_stock(symbol).await;
+    stock.other.profile = profile;
+
+    Ok(stock.clone())
+}
+
+// A copy to fetch into, so network calls are made without holding the cache lock
+pub async fn clone_stock(index: usize) -> Stock {
+    CACHE.lock().await.get(index).unwrap().clone()
+}
 
-    return MutexGuard::map(CACHE.lock().await, |d| d.get_mut(length).unwrap());
+// Puts back the sections fetched into a copy, section by section, so tasks that fetched the same
+// stock at the same time keep each other's data. Returns the stock as it is now cached.
+pub async fn store_stock(stock: Stock) -> Stock {
+    let index = stock.cache_index.unwrap();
+    let mut cache = CACHE.lock().await;
+    cache[index].merge(stock);
+    cache[index].clone()
 }
 
 pub async fn stock_index_in_cache(ticker: String) -> Option<usize> {
@@ -40,10 +73,10 @@ pub async fn save() {
 
     match write_to_file {
         Ok(_v) => {
-            println!("WROTE TO FILE");
+            tracing::info!(stocks = CACHE.lock().await.len(), "wrote the cache to cache.json");
         }
         Err(e) => {
-            println!("Could not write to file: {}", e);
+            tracing::warn!(error = %e, "could not write the cache to cache.json");
         }
     }
 }
@@ -57,7 +90,7 @@ pub fn state_from_json() -> Vec<Stock> {
             cache_string = v;
         }
         Err(e) => {
-            println!("{}", e);
+            tracing::warn!(error = %e, "could not read cache.json");
         }
     }
 
@@ -66,7 +99,7 @@ pub fn state_from_json() -> Vec<Stock> {
     match string_to_struct {
         Ok(v) => v,
         Err(e) => {
-            println!("{}", e);
+            tracing::warn!(error = %e, "could not parse cache.json");
             Vec::new()
         }
     }
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::errors::ApiError;
+use crate::helper_structs::TimePeriod;
+use crate::rules::Source;
+use crate::stock::Stock;
+
+// Enough for a company and its closest competitors without holding up the request for long
+pub const MAX_SYMBOLS: usize = 10;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareQuery {
+    /// Comma separated tickers, e.g. AAPL,MSFT,GOOGL
+    pub symbols: String,
+    /// Comma separated source.field pairs, e.g. income.revenue,ratios.returnOnEquity
+    pub fields: String,
+    /// annual (the default), quarter or ttm
+    pub period: Option<String>,
+    /// How many periods, 10 when not given
+    pub limit: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct CompareField {
+    pub source: Source,
+    // Name of the field as the api returns it, e.g. "revenue"
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct StockComparison {
+    pub period: String,
+    pub symbols: Vec<String>,
+    // Oldest first: fiscal years, calendar quarters like 2023-Q3, or TTM
+    pub labels: Vec<String>,
+    pub series: Vec<FieldSeries>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct FieldSeries {
+    pub field: CompareField,
+    pub stocks: Vec<StockSeries>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct StockSeries {
+    pub symbol: String,
+    // One value per label, null where the stock did not report the period
+    pub values: Vec<Option<f64>>,
+}
+
+// The labelled values of every field for one stock, in the order the fields were asked for
+pub struct StockPoints {
+    pub symbol: String,
+    pub fields: Vec<Vec<(String, Option<f64>)>>,
+}
+
+pub fn parse_symbols(symbols: &str) -> Result<Vec<String>, String> {
+    let mut parsed: Vec<String> = vec![];
+
+    for symbol in symbols.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let symbol = symbol.to_uppercase();
+        if !parsed.contains(&symbol) {
+            parsed.push(symbol);
+        }
+    }
+
+    match parsed.len() {
+        0 => Err("symbols has to list at least one ticker".to_string()),
+        n if n > MAX_SYMBOLS => Err(format!(
+            "at most {} symbols can be compared, got {}",
+            MAX_SYMBOLS, n
+        )),
+        _ => Ok(parsed),
+    }
+}
+
+pub fn parse_fields(fields: &str) -> Result<Vec<CompareField>, String> {
+    let mut parsed = vec![];
+
+    for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let Some((source, name)) = field.split_once('.') else {
+            return Err(format!("{} is not written as source.field", field));
+        };
+
+        let source: Source = serde_json::from_value(Value::String(source.to_string()))
+            .map_err(|_| format!("unknown source {} in {}", source, field))?;
+
+        if !matches!(
+            source,
+            Source::Income
+                | Source::Balance
+                | Source::CashFlow
+                | Source::Ratios
+                | Source::KeyMetrics
+        ) {
+            return Err(format!("{} is not reported per period", field));
+        }
+
+        parsed.push(CompareField {
+            source,
+            name: name.to_string(),
+        });
+    }
+
+    if parsed.is_empty() {
+        return Err("fields has to list at least one source.field".to_string());
+    }
+
+    Ok(parsed)
+}
+
+pub async fn collect(
+    stock: &mut Stock,
+    fields: &[CompareField],
+    period: &TimePeriod,
+) -> Result<StockPoints, ApiError> {
+    let mut fetched: Vec<Source> = vec![];
+
+    for field in fields {
+        if !fetched.contains(&field.source) {
+            stock.fetch_source(field.source, period.clone()).await?;
+            fetched.push(field.source);
+        }
+    }
+
+    Ok(StockPoints {
+        symbol: stock.ticker.clone(),
+        fields: fields
+            .iter()
+            .map(|field| {
+                stock
+                    .rows(field.source, period)
+                    .iter()
+                    .filter_map(|row| {
+                        let label = label(row, period)?;
+                        Some((label, row.get(&field.name)?.as_f64()))
+                    })
+                    .collect()
+            })
+            .collect(),
+    })
+}
+
+// Lines the stocks up on the periods any of them reported. A field none of the rows contain
+// is most likely misspelled and is reported instead of answering with nothing but nulls.
+pub fn align(
+    period: &TimePeriod,
+    fields: Vec<CompareField>,
+    stocks: Vec<StockPoints>,
+) -> Result<StockComparison, String> {
+    let mut labels: Vec<String> = stocks
+        .iter()
+        .flat_map(|s| s.fields.iter().flatten().map(|(label, _)| label.clone()))
+        .collect();
+    labels.sort();
+    labels.dedup();
+
+    let mut series = vec![];
+
+    for (i, field) in fields.into_iter().enumerate() {
+        let reported = stocks.iter().any(|s| !s.fields[i].is_empty());
+        let known = stocks
+            .iter()
+            .any(|s| s.fields[i].iter().any(|(_, v)| v.is_some()));
+
+        if reported && !known {
+            return Err(format!("no stock reports a field called {}", field.name));
+        }
+
+        let stocks = stocks
+            .iter()
+            .map(|s| StockSeries {
+                symbol: s.symbol.clone(),
+                values: labels
+                    .iter()
+                    .map(|label| {
+                        s.fields[i]
+                            .iter()
+                            .find(|(l, _)| l == label)
+                            .and_then(|(_, v)| *v)
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        series.push(FieldSeries { field, stocks });
+    }
+
+    Ok(StockComparison {
+        period: period_name(period).to_string(),
+        symbols: stocks.into_iter().map(|s| s.symbol).collect(),
+        labels,
+        series,
+    })
+}
+
+// Fiscal years end in different months, so annual rows are matched on the year they end in
+// and quarters on the calendar quarter they end in
+fn label(row: &Value, period: &TimePeriod) -> Option<String> {
+    let date = row["date"].as_str();
+
+    match period {
+        TimePeriod::TTM() => Some("TTM".to_string()),
+        TimePeriod::Quarter(_) => {
+            let date = date?;
+            let month: u32 = date.get(5..7)?.parse().ok()?;
+            Some(format!("{}-Q{}", date.get(..4)?, month.div_ceil(3)))
+        }
+        _ => date?.get(..4).map(str::to_string),
+    }
+}
+
+fn period_name(period: &TimePeriod) -> &'static str {
+    match period {
+        TimePeriod::Annual(_) => "annual",
+        TimePeriod::Quarter(_) => "quarter",
+        TimePeriod::TTM() => "ttm",
+        TimePeriod::NA() => "all",
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::errors::ApiError;
+use crate::helper_structs::TimePeriod;
+use crate::stock::Stock;
+use crate::utils::growth_streak;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DividendHistory {
+    pub symbol: String,
+    // Consecutive years, newest first, in which the dividend per share went up
+    pub growth_streak: usize,
+    // Consecutive years, newest first, in which any dividend was paid
+    pub payment_streak: usize,
+    pub cagr_3_years: Option<f64>,
+    pub cagr_5_years: Option<f64>,
+    pub cagr_10_years: Option<f64>,
+    pub years: Vec<DividendYear>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DividendYear {
+    pub date: String,
+    // Positive amount, the cash flow statement reports it as an outflow
+    pub dividends_paid: Option<f64>,
+    pub dividend_per_share: Option<f64>,
+    pub eps: Option<f64>,
+    pub net_income: Option<f64>,
+    pub free_cash_flow: Option<f64>,
+    pub payout_ratio_earnings: Option<f64>,
+    pub payout_ratio_free_cash_flow: Option<f64>,
+    // How many times earnings and free cash flow cover the dividend
+    pub earnings_coverage: Option<f64>,
+    pub free_cash_flow_coverage: Option<f64>,
+}
+
+// Ten years of growth are measured from the eleventh newest year
+pub const YEARS: u8 = 11;
+
+impl DividendHistory {
+    pub async fn fetch(stock: &mut Stock, period: TimePeriod) -> Result<(), ApiError> {
+        stock.income(period.clone()).await?;
+        stock.cash(period).await
+    }
+
+    pub fn from_stock(stock: &Stock) -> Self {
+        let years = dividend_years(stock);
+        let per_share: Vec<Option<f64>> = years.iter().map(|y| y.dividend_per_share).collect();
+
+        Self {
+            symbol: stock.ticker.clone(),
+            growth_streak: growth_streak(&per_share),
+            payment_streak: years
+                .iter()
+                .take_while(|y| y.dividends_paid.unwrap_or_default() > 0.0)
+                .count(),
+            cagr_3_years: cagr(&per_share, 3),
+            cagr_5_years: cagr(&per_share, 5),
+            cagr_10_years: cagr(&per_share, 10),
+            years,
+        }
+    }
+}
+
+// One row per annual cash flow statement, matched to the income statement of the same date
+pub fn dividend_years(stock: &Stock) -> Vec<DividendYear> {
+    stock
+        .statements
+        .annual_cash
+        .0
+        .iter()
+        .map(|cash| {
+            let income = stock
+                .statements
+                .annual_income
+                .0
+                .iter()
+                .find(|income| income.date == cash.date);
+
+            let dividends_paid = cash.dividends_paid.map(f64::abs);
+            let shares = income.and_then(|i| i.weighted_average_shs_out);
+            let net_income = income.and_then(|i| i.net_income);
+            let free_cash_flow = cash.free_cash_flow;
+
+            DividendYear {
+                date: cash.date.clone(),
+                dividends_paid,
+                dividend_per_share: divide(dividends_paid, shares),
+                eps: income.and_then(|i| i.eps),
+                net_income,
+                free_cash_flow,
+                payout_ratio_earnings: divide(dividends_paid, net_income),
+                payout_ratio_free_cash_flow: divide(dividends_paid, free_cash_flow),
+                earnings_coverage: divide(net_income, dividends_paid),
+                free_cash_flow_coverage: divide(free_cash_flow, dividends_paid),
+            }
+        })
+        .collect()
+}
+
+// Compound annual growth from `years` years ago to the latest value
+fn cagr(values: &[Option<f64>], years: usize) -> Option<f64> {
+    let newest = (*values.first()?)?;
+    let oldest = (*values.get(years)?)?;
+
+    if newest <= 0.0 || oldest <= 0.0 {
+        return None;
+    }
+
+    Some((newest / oldest).powf(1.0 / years as f64) - 1.0)
+}
+
+fn divide(numerator: Option<f64>, denominator: Option<f64>) -> Option<f64> {
+    match (numerator, denominator) {
+        (Some(n), Some(d)) if d != 0.0 => Some(n / d),
+        _ => None,
+    }
+}
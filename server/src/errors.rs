@@ -0,0 +1,202 @@
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Path, Query};
+use axum::http::request::Parts;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{BoxError, Json};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+// Every failed API request answers with {"error": {"code", "message", "details"}}
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    // Stable and machine readable, e.g. "unknown_symbol", the message is for people
+    #[schema(value_type = String)]
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn unknown_symbol(symbol: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "unknown_symbol",
+            format!("no company is listed as {}", symbol),
+        )
+        .with_details(json!({ "symbol": symbol }))
+    }
+
+    pub fn unknown_screen(name: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "unknown_screen",
+            format!("there is no screen called {}", name),
+        )
+        .with_details(json!({ "screen": name }))
+    }
+
+    pub fn not_found(what: &str, id: impl ToString) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            format!("{} {} does not exist", what, id.to_string()),
+        )
+    }
+
+    pub fn no_data(symbol: &str, what: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "no_data",
+            format!("the data provider has no {} for {}", what, symbol),
+        )
+        .with_details(json!({ "symbol": symbol }))
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ApiError,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(ErrorResponse { error: self })).into_response()
+    }
+}
+
+// Upstream failures: its rate limit is passed on as 429, being unreachable is 503 and anything
+// else it answered with, including a body that does not parse, is a bad gateway
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        let (status, code) = match e.status() {
+            Some(StatusCode::TOO_MANY_REQUESTS) => (StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+            _ if e.is_timeout() || e.is_connect() => {
+                (StatusCode::SERVICE_UNAVAILABLE, "upstream_unavailable")
+            }
+            _ => (StatusCode::BAD_GATEWAY, "upstream_error"),
+        };
+
+        let mut error = Self::new(status, code, "the financial data provider request failed");
+
+        if let Some(upstream) = e.status() {
+            error = error.with_details(json!({ "upstream_status": upstream.as_u16() }));
+        }
+
+        error
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), "invalid_body", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(rejection.status(), "invalid_path", rejection.body_text())
+    }
+}
+
+// Path, Query and Json that answer malformed requests with the error envelope instead of plain text
+pub struct ApiPath<T>(pub T);
+pub struct ApiQuery<T>(pub T);
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
+
+// Upper cases the symbol, which may contain letters, digits and the . - ^ = used for share
+// classes, foreign listings and indexes
+pub fn validate_symbol(symbol: &str) -> Result<String, ApiError> {
+    let valid = !symbol.is_empty()
+        && symbol.len() <= 15
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '^' | '='));
+
+    if !valid {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_symbol",
+            format!("{} is not a valid ticker symbol", symbol),
+        )
+        .with_details(json!({ "symbol": symbol })));
+    }
+
+    Ok(symbol.to_uppercase())
+}
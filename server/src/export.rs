@@ -0,0 +1,333 @@
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use rust_xlsxwriter::{Format as CellFormat, Workbook, XlsxError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::IntoParams;
+
+use crate::errors::ApiError;
+use crate::helper_structs::{ResponseCache, TimePeriod};
+use crate::jobs::PassedStock;
+use crate::resources;
+use crate::rules::{ScreenDefinition, Source};
+use crate::stock::Stock;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StockExportQuery {
+    /// csv (the default) or xlsx
+    pub format: Option<String>,
+    /// Comma separated, any of income, balance, cash_flow, ratios and key_metrics, all when not given
+    pub sections: Option<String>,
+    /// annual (the default), quarter or ttm
+    pub period: Option<String>,
+    /// How many periods, 10 when not given
+    pub limit: Option<u8>,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// csv (the default) or xlsx
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Xlsx,
+}
+
+// A titled block of rows, a worksheet in xlsx and a section of the file in csv
+pub struct Table {
+    pub title: String,
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+const SECTIONS: [Source; 5] = [
+    Source::Income,
+    Source::Balance,
+    Source::CashFlow,
+    Source::Ratios,
+    Source::KeyMetrics,
+];
+
+impl Format {
+    pub fn parse(format: Option<&str>) -> Result<Self, String> {
+        match format.unwrap_or("csv") {
+            "csv" => Ok(Self::Csv),
+            "xlsx" => Ok(Self::Xlsx),
+            other => Err(format!("unknown format {}, expected csv or xlsx", other)),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+}
+
+pub fn parse_sections(sections: Option<&str>) -> Result<Vec<Source>, String> {
+    let Some(sections) = sections else {
+        return Ok(SECTIONS.to_vec());
+    };
+
+    let mut parsed = vec![];
+
+    for section in sections.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let source: Source = serde_json::from_value(Value::String(section.to_string()))
+            .ok()
+            .filter(|source| SECTIONS.contains(source))
+            .ok_or_else(|| {
+                format!(
+                    "unknown section {}, expected income, balance, cash_flow, ratios or key_metrics",
+                    section
+                )
+            })?;
+
+        if !parsed.contains(&source) {
+            parsed.push(source);
+        }
+    }
+
+    if parsed.is_empty() {
+        return Err("sections has to list at least one section".to_string());
+    }
+
+    Ok(parsed)
+}
+
+// One table per section with a row per period, newest first, and the columns in the order the
+// api defines them. Balance sheets and cash flows have no TTM version and are left out for it.
+pub async fn stock_tables(
+    stock: &mut Stock,
+    sections: &[Source],
+    period: TimePeriod,
+) -> Result<Vec<Table>, ApiError> {
+    let ttm = period == TimePeriod::TTM();
+    let mut tables = vec![];
+
+    for section in sections {
+        let table = match section {
+            Source::Income => table(
+                "Income",
+                &resources::income(stock, period.clone()).await?.rows,
+            ),
+            Source::Balance if !ttm => table(
+                "Balance Sheet",
+                &resources::balance(stock, period.clone()).await?.rows,
+            ),
+            Source::CashFlow if !ttm => table(
+                "Cash Flow",
+                &resources::cash_flow(stock, period.clone()).await?.rows,
+            ),
+            Source::Ratios if ttm => table("Ratios", &resources::ratios_ttm(stock).await?.rows),
+            Source::Ratios => table(
+                "Ratios",
+                &resources::ratios(stock, period.clone()).await?.rows,
+            ),
+            Source::KeyMetrics if ttm => table(
+                "Key Metrics",
+                &resources::key_metrics_ttm(stock).await?.rows,
+            ),
+            Source::KeyMetrics => table(
+                "Key Metrics",
+                &resources::key_metrics(stock, period.clone()).await?.rows,
+            ),
+            _ => continue,
+        }
+        .map_err(|e| export_failed(e.to_string()))?;
+
+        if !table.rows.is_empty() {
+            tables.push(table);
+        }
+    }
+
+    Ok(tables)
+}
+
+// The stocks that passed in the screen's order, with the rank and score when it ranks them and
+// the value behind every criterion and ranking factor. Results restored after a restart have no
+// evaluations, their stocks are listed with the rank and score alone.
+pub fn screen_table(screen: &ScreenDefinition, results: &ResponseCache) -> Table {
+    let ranked = !results.ranked.is_empty();
+    let passed: Vec<(&String, Option<PassedStock>)> = results
+        .data
+        .iter()
+        .map(|symbol| {
+            let evaluation = results.evaluations.iter().find(|e| &e.symbol == symbol);
+            (symbol, evaluation.map(|e| PassedStock::new(screen, e)))
+        })
+        .collect();
+
+    let mut header = vec!["symbol".to_string()];
+    if ranked {
+        header.extend(["rank".to_string(), "score".to_string()]);
+    }
+    let first = passed.iter().find_map(|(_, stock)| stock.as_ref());
+    let metrics = first.map_or(0, |stock| stock.metrics.len());
+    if let Some(stock) = first {
+        header.extend(stock.metrics.iter().map(|m| m.name.clone()));
+    }
+
+    let rows = passed
+        .iter()
+        .map(|(symbol, stock)| {
+            let mut row = vec![symbol.to_string()];
+
+            if ranked {
+                match results.ranked.iter().find(|r| &&r.symbol == symbol) {
+                    Some(r) => row.extend([r.rank.to_string(), r.score.to_string()]),
+                    None => row.extend([String::new(), String::new()]),
+                }
+            }
+
+            match stock {
+                Some(stock) => row.extend(
+                    stock
+                        .metrics
+                        .iter()
+                        .map(|m| m.value.map(|v| v.to_string()).unwrap_or_default()),
+                ),
+                None => row.extend(vec![String::new(); metrics]),
+            }
+            row
+        })
+        .collect();
+
+    Table {
+        title: screen.name.clone(),
+        header,
+        rows,
+    }
+}
+
+// Answers with the tables as a file to save rather than show
+pub fn download(format: Format, name: &str, tables: &[Table]) -> Result<Response, ApiError> {
+    let body = match format {
+        Format::Csv => to_csv(tables).map_err(|e| export_failed(e.to_string()))?,
+        Format::Xlsx => to_xlsx(tables).map_err(|e| export_failed(e.to_string()))?,
+    };
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", name, format.extension()),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+pub fn export_failed(message: String) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "export_failed", message)
+}
+
+// Goes through the csv writer, which keeps the fields in declaration order, and reads the
+// result back as text
+fn table<T: Serialize>(title: &str, rows: &[T]) -> Result<Table, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+
+    let written = writer.into_inner().map_err(|e| e.into_error())?;
+    let mut reader = csv::Reader::from_reader(written.as_slice());
+
+    let header = reader.headers()?.iter().map(str::to_string).collect();
+    let rows = reader
+        .records()
+        .map(|record| record.map(|r| r.iter().map(str::to_string).collect()))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Table {
+        title: title.to_string(),
+        header,
+        rows,
+    })
+}
+
+// A single table is a plain csv file, several are written one after another, each under its
+// title and separated by an empty line
+fn to_csv(tables: &[Table]) -> Result<Vec<u8>, csv::Error> {
+    let mut out = vec![];
+
+    for (i, table) in tables.iter().enumerate() {
+        // Written directly, the csv writer quotes a record with one empty field
+        if i > 0 {
+            out.push(b'\n');
+        }
+
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+
+        if tables.len() > 1 {
+            writer.write_record([&table.title])?;
+        }
+
+        writer.write_record(&table.header)?;
+        for row in &table.rows {
+            writer.write_record(row)?;
+        }
+
+        out = writer.into_inner().map_err(|e| e.into_error())?;
+    }
+
+    Ok(out)
+}
+
+fn to_xlsx(tables: &[Table]) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = CellFormat::new().set_bold();
+
+    for table in tables {
+        let sheet = workbook.add_worksheet();
+        // Worksheet names are limited to 31 characters and a few are not allowed at all
+        let name: String = table
+            .title
+            .chars()
+            .filter(|c| !matches!(c, '[' | ']' | ':' | '*' | '?' | '/' | '\\'))
+            .take(31)
+            .collect();
+        // Nothing left of the title, e.g. a screen named only with characters that are not allowed
+        if name.trim().is_empty() {
+            sheet.set_name("Screen")?;
+        } else {
+            sheet.set_name(name)?;
+        }
+
+        for (col, title) in table.header.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, title, &bold)?;
+        }
+
+        for (row, values) in table.rows.iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                let (row, col) = (row as u32 + 1, col as u16);
+
+                match value.parse::<f64>() {
+                    Ok(number) if number.is_finite() => sheet.write_number(row, col, number)?,
+                    _ if value.is_empty() => sheet,
+                    _ => sheet.write_string(row, col, value)?,
+                };
+            }
+        }
+
+        sheet.set_freeze_panes(1, 1)?;
+        sheet.autofit();
+    }
+
+    workbook.save_to_buffer()
+}
@@ -1,22 +1,56 @@
 use crate::helper_structs::TimePeriod;
-use crate::utils;
+use crate::{telemetry, utils};
 
 use core::fmt::Debug;
 use serde::de::DeserializeOwned;
 
+use once_cell::sync::Lazy;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
-use tokio::time;
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant};
+use tracing::field::Empty;
+use tracing::Instrument;
 
-static mut COUNTER: u64 = 0;
-static mut ERROR_COUNTER: u64 = 0;
+// Everything but the unreserved characters is escaped where the symbol goes into the url
+const SYMBOL_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'.')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'~');
 
-async fn api_handler<T>(
+// Shared by every request so concurrent fetches stay within the API's budget
+static REQUESTS_PER_SECOND: AtomicU64 = AtomicU64::new(5);
+static NEXT_REQUEST: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::new(Instant::now()));
+
+pub fn set_requests_per_second(requests: u64) {
+    REQUESTS_PER_SECOND.store(requests.max(1), Ordering::Relaxed);
+}
+
+// Hands out request slots spaced evenly at the configured rate and sleeps until ours comes up
+async fn wait_for_rate_limit() {
+    let spacing = Duration::from_secs_f64(1.0 / REQUESTS_PER_SECOND.load(Ordering::Relaxed) as f64);
+
+    let slot = {
+        let mut next = NEXT_REQUEST.lock().await;
+        let slot = (*next).max(Instant::now());
+        *next = slot + spacing;
+        slot
+    };
+
+    time::sleep_until(slot).await;
+}
+
+// The endpoint comes from T, the response is parsed as R
+async fn api_handler<T, R>(
     symbol: &String,
     period: &TimePeriod,
     q: String,
-) -> Result<Vec<T>, reqwest::Error>
+) -> Result<R, reqwest::Error>
 where
-    T: DeserializeOwned + Debug + 'static,
+    T: 'static,
+    R: DeserializeOwned,
 {
     let limit: String;
     let period_type: String;
@@ -44,50 +78,102 @@ where
         }
     }
 
+    let escaped = utf8_percent_encode(symbol, SYMBOL_ESCAPE);
+
     if end_point == "advanced_levered_discounted_cash_flow" {
         url = format!(
             "https://financialmodelingprep.com/api/v4/{}/?symbol={}&apikey={}&limit={}&period={}&{}",
-            end_point, &symbol, key, limit, period_type, q
+            end_point, escaped, key, limit, period_type, q
         );
     } else {
         url = format!(
             "https://financialmodelingprep.com/api/v3/{}/{}?apikey={}&limit={}&period={}&{}",
-            end_point, &symbol, key, limit, period_type, q
+            end_point, escaped, key, limit, period_type, q
         );
     }
 
-    let mut interval = time::interval(Duration::from_secs_f32(0.2));
+    let span = tracing::debug_span!(
+        "provider_call",
+        symbol = %symbol,
+        endpoint = %end_point,
+        period = ?period,
+        status = Empty,
+        bytes = Empty,
+        waited_ms = Empty,
+    );
+    telemetry::count_upstream_call();
+
+    async move {
+        let waiting = Instant::now();
+        wait_for_rate_limit().await;
+        let sent = Instant::now();
+        tracing::Span::current().record("waited_ms", (sent - waiting).as_millis() as u64);
+
+        // The url carries the api key, errors are logged and handed to callers without it
+        let result = fetch::<R>(url).await.map_err(reqwest::Error::without_url);
+
+        telemetry::record_upstream(
+            &end_point,
+            sent - waiting,
+            sent.elapsed(),
+            result.as_ref().err(),
+        );
 
-    unsafe {
-        println!("{}-{}\t{}", COUNTER, ERROR_COUNTER, url);
-        COUNTER += 1;
-        interval.tick().await;
-        interval.tick().await;
+        result
     }
+    .instrument(span)
+    .await
+}
 
-    let resp: Vec<T> = reqwest::get(url).await?.json::<Vec<T>>().await?;
+async fn fetch<R: DeserializeOwned>(url: String) -> Result<R, reqwest::Error> {
+    let response = reqwest::get(url).await?;
+    let span = tracing::Span::current();
+    span.record("status", response.status().as_u16());
 
-    Ok(resp)
+    let body = response.error_for_status()?.bytes().await?;
+    span.record("bytes", body.len());
+
+    // Decoded through reqwest so a malformed body is reported like every other failure
+    reqwest::Response::from(axum::http::Response::new(body))
+        .json::<R>()
+        .await
 }
 
 pub async fn api<T>(period: &TimePeriod, symbol: &String, q: String) -> Vec<T>
 where
     T: DeserializeOwned + Debug + 'static,
 {
-    let mut statements = Vec::new();
-    let result = api_handler::<T>(&symbol, &period, q).await;
+    api_result(period, symbol, q).await.unwrap_or_default()
+}
+
+// Like api, but hands the failure to callers that report it instead of treating it as no data
+pub async fn api_result<T>(
+    period: &TimePeriod,
+    symbol: &String,
+    q: String,
+) -> Result<Vec<T>, reqwest::Error>
+where
+    T: DeserializeOwned + Debug + 'static,
+{
+    let result = api_handler::<T, Vec<T>>(symbol, period, q).await;
 
-    match result {
-        Ok(v) => {
-            statements = v;
-        }
+    if let Err(e) = &result {
+        tracing::warn!(symbol = %symbol, error = %e, "provider call failed");
+    }
+
+    result
+}
+
+// For endpoints that answer with a single object instead of a list
+pub async fn api_object<T>(period: &TimePeriod, symbol: &String, q: String) -> Option<T>
+where
+    T: DeserializeOwned + Debug + 'static,
+{
+    match api_handler::<T, T>(symbol, period, q).await {
+        Ok(v) => Some(v),
         Err(e) => {
-            println!("{}", e);
-            unsafe {
-                ERROR_COUNTER += 1;
-            }
+            tracing::warn!(symbol = %symbol, error = %e, "provider call failed");
+            None
         }
     }
-
-    statements
 }
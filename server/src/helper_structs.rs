@@ -1,12 +1,16 @@
 use axum::Json;
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::openapi::schema::{Array, ArrayBuilder, OneOfBuilder, Ref};
+use utoipa::ToSchema;
 
+use crate::ranking::RankedStock;
+use crate::rules::{Evaluation, ScreenDefinition};
 use crate::stock::Stock;
 use crate::{metrics::Metrics, statements::Statements};
 
 // Use struct instead of tuple for better readability
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TimePeriod {
     Annual(u8),
     Quarter(u8),
@@ -18,6 +22,8 @@ pub enum TimePeriod {
 #[serde(rename_all = "camelCase")]
 pub struct AvailableTraded {
     pub symbol: String,
+    #[serde(default)]
+    pub name: Option<String>,
     pub exchange_short_name: String,
     pub type_: String,
 }
@@ -26,13 +32,31 @@ pub struct AvailableTraded {
 pub struct ResponseCache {
     pub endpoint: String,
     pub data: Json<Vec<String>>,
+    pub evaluations: Vec<Evaluation>,
+    pub ranked: Vec<RankedStock>,
+    pub finished_at: DateTime<Utc>,
+    // Cache index of each stock that passed and the data stamp of what the screen read from it
+    pub constituents: Vec<(usize, u64)>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl ResponseCache {
+    // Old enough to expire or built from rows the screen reads that have since been updated
+    pub fn is_stale(&self, screen: &ScreenDefinition, stocks: &[Stock]) -> bool {
+        screen.is_expired(self.finished_at)
+            || self.constituents.iter().any(|(i, stamp)| {
+                stocks
+                    .get(*i)
+                    .is_none_or(|stock| screen.data_stamp(stock) != *stamp)
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct IncomeStatement {
     pub date: String,
     pub period: String,
+    pub filling_date: Option<String>,
     pub reported_currency: Option<String>,
     pub revenue: Option<f64>,
     pub cost_of_revenue: Option<f64>,
@@ -64,10 +88,11 @@ pub struct IncomeStatement {
     pub weighted_average_shs_out_dil: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BalanceSheetStatement {
     pub date: String,
+    pub filling_date: Option<String>,
     pub reported_currency: String,
     pub cash_and_cash_equivalents: Option<f64>,
     pub short_term_investments: Option<f64>,
@@ -114,10 +139,11 @@ pub struct BalanceSheetStatement {
     pub net_debt: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CashFlowStatement {
     pub date: String,
+    pub filling_date: Option<String>,
     pub reported_currency: String,
     pub net_income: Option<f64>,
     pub depreciation_and_amortization: Option<f64>,
@@ -151,7 +177,7 @@ pub struct CashFlowStatement {
     pub free_cash_flow: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Ratios {
     pub date: String,
@@ -206,7 +232,7 @@ pub struct Ratios {
     pub priceFairValue: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RatiosTTM {
     pub dividend_yiel_TTM: Option<f64>,
@@ -269,7 +295,7 @@ pub struct RatiosTTM {
     pub dividend_per_share_TTM: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct KeyMetrics {
     pub date: String,
@@ -332,7 +358,7 @@ pub struct KeyMetrics {
     pub capex_per_share: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct KeyMetricsTTM {
     pub revenue_per_share_TTM: Option<f64>,
@@ -396,7 +422,7 @@ pub struct KeyMetricsTTM {
     pub debt_to_market_cap_TTM: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Profile {
     pub symbol: Option<String>,
@@ -439,6 +465,23 @@ pub struct Profile {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
+pub struct HistoricalPriceFull {
+    pub symbol: Option<String>,
+    #[serde(default)]
+    pub historical: Vec<HistoricalPrice>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoricalPrice {
+    pub date: String,
+    pub close: Option<f64>,
+    // Adjusted for splits and dividends
+    pub adj_close: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct AdvancedLeveredDiscountedCashFlow {
     pub year: Option<String>,
     pub symbol: Option<String>,
@@ -484,7 +527,7 @@ pub struct NeededData {
     pub key_metrics_ttm: (bool, TimePeriod),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct FetchStats {
     pub last_pull_length: usize,
     pub last_pull_time: Option<NaiveDate>,
@@ -499,6 +542,30 @@ impl FetchStats {
     }
 }
 
+// Takes the fetched section when it was pulled later, or on the same day with more rows, than the
+// one in place. A copy that did not fetch the section leaves what another task stored untouched.
+pub fn keep_newer<T>(current: &mut (Vec<T>, FetchStats), fetched: (Vec<T>, FetchStats)) {
+    if (fetched.1.last_pull_time, fetched.0.len()) > (current.1.last_pull_time, current.0.len()) {
+        *current = fetched;
+    }
+}
+
+// Schema of a section stored as a (rows, FetchStats) tuple, which serializes as a two item array
+pub fn fetched_rows(rows: &str) -> Array {
+    ArrayBuilder::new()
+        .items(
+            OneOfBuilder::new()
+                .item(ArrayBuilder::new().items(Ref::from_schema_name(rows)))
+                .item(Ref::from_schema_name("FetchStats")),
+        )
+        .min_items(Some(2))
+        .max_items(Some(2))
+        .description(Some(format!(
+            "The {rows} rows followed by their FetchStats"
+        )))
+        .build()
+}
+
 pub trait StockInfo {
     fn length_of_annual_statement(&self) -> usize;
     fn length_of_quarter_statement(&self) -> usize;
@@ -0,0 +1,162 @@
+use std::fs;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::ranking::RankedStock;
+use crate::rules::ScreenDefinition;
+use crate::RUNS;
+
+const RUNS_FILE: &str = "runs.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ScreenRun {
+    pub id: usize,
+    pub screen: String,
+    // The definition the screen had when it ran, so older runs still make sense after edits
+    pub definition: ScreenDefinition,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    // Newest reporting period among the stocks that passed
+    pub data_as_of: Option<String>,
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub ranked: Vec<RankedStock>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct RunSummary {
+    pub id: usize,
+    pub screen: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub data_as_of: Option<String>,
+    pub passed: usize,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct RunDiff {
+    pub from: RunSummary,
+    pub to: RunSummary,
+    pub entered: Vec<String>,
+    pub exited: Vec<String>,
+    pub stayed: Vec<String>,
+}
+
+impl From<&ScreenRun> for RunSummary {
+    fn from(run: &ScreenRun) -> Self {
+        Self {
+            id: run.id,
+            screen: run.screen.clone(),
+            started_at: run.started_at,
+            finished_at: run.finished_at,
+            data_as_of: run.data_as_of.clone(),
+            passed: run.symbols.len(),
+        }
+    }
+}
+
+impl RunDiff {
+    pub fn new(from: &ScreenRun, to: &ScreenRun) -> Self {
+        let (stayed, entered) = to
+            .symbols
+            .iter()
+            .cloned()
+            .partition(|symbol| from.symbols.contains(symbol));
+
+        Self {
+            from: RunSummary::from(from),
+            to: RunSummary::from(to),
+            entered,
+            exited: from
+                .symbols
+                .iter()
+                .filter(|symbol| !to.symbols.contains(symbol))
+                .cloned()
+                .collect(),
+            stayed,
+        }
+    }
+}
+
+// Stores the run with the next id and writes the history to disk
+pub async fn record(mut run: ScreenRun) {
+    let mut runs = RUNS.lock().await;
+
+    run.id = runs.iter().map(|r| r.id).max().unwrap_or_default() + 1;
+    runs.push(run);
+
+    save(&runs);
+}
+
+// Oldest first
+pub async fn runs(screen: &str) -> Vec<RunSummary> {
+    RUNS.lock()
+        .await
+        .iter()
+        .filter(|run| run.screen == screen)
+        .map(RunSummary::from)
+        .collect()
+}
+
+pub async fn find_run(screen: &str, id: usize) -> Option<ScreenRun> {
+    RUNS.lock()
+        .await
+        .iter()
+        .find(|run| run.screen == screen && run.id == id)
+        .cloned()
+}
+
+// The most recent run made with the screen's current definition, unless it has expired
+pub async fn latest_run(screen: &ScreenDefinition) -> Option<ScreenRun> {
+    RUNS.lock()
+        .await
+        .iter()
+        .rev()
+        .find(|run| run.screen == screen.name && &run.definition == screen)
+        .filter(|run| !screen.is_expired(run.finished_at))
+        .cloned()
+}
+
+// Without ids, compares the two most recent runs of the screen
+pub async fn diff(screen: &str, from: Option<usize>, to: Option<usize>) -> Option<RunDiff> {
+    let runs = RUNS.lock().await;
+    let runs: Vec<&ScreenRun> = runs.iter().filter(|run| run.screen == screen).collect();
+
+    let find = |id: usize| runs.iter().find(|run| run.id == id).copied();
+
+    let to = match to {
+        Some(id) => find(id)?,
+        None => *runs.last()?,
+    };
+    let from = match from {
+        Some(id) => find(id)?,
+        None => *runs.iter().rev().find(|run| run.id < to.id)?,
+    };
+
+    Some(RunDiff::new(from, to))
+}
+
+fn save(runs: &[ScreenRun]) {
+    let runs_as_json = serde_json::to_string(runs).unwrap();
+
+    if let Err(e) = fs::write(RUNS_FILE, runs_as_json) {
+        tracing::warn!(file = RUNS_FILE, error = %e, "could not write the screen runs");
+    }
+}
+
+pub fn state_from_json() -> Vec<ScreenRun> {
+    let contents = match fs::read_to_string(RUNS_FILE) {
+        Ok(v) => v,
+        Err(_e) => return vec![],
+    };
+
+    match serde_json::from_str(&contents) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!(file = RUNS_FILE, error = %e, "could not parse the screen runs");
+            vec![]
+        }
+    }
+}
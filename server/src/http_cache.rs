@@ -0,0 +1,138 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::helper_structs::FetchStats;
+use crate::stock::Stock;
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+// ETag and Last-Modified of a response built from a cached stock. The stock only changes when
+// one of its statements or metrics is pulled again, which FetchStats records.
+pub struct Validators {
+    etag: String,
+    last_modified: Option<NaiveDateTime>,
+}
+
+impl Validators {
+    pub fn for_stock(stock: &Stock) -> Self {
+        let statements = &stock.statements;
+        let metrics = &stock.metrics;
+        let pulls: [(&FetchStats, usize); 13] = [
+            (
+                &statements.annual_income.1,
+                statements.annual_income.0.len(),
+            ),
+            (
+                &statements.quarter_income.1,
+                statements.quarter_income.0.len(),
+            ),
+            (&statements.ttm_income.1, statements.ttm_income.0.len()),
+            (
+                &statements.annual_balance.1,
+                statements.annual_balance.0.len(),
+            ),
+            (
+                &statements.quarter_balance.1,
+                statements.quarter_balance.0.len(),
+            ),
+            (&statements.annual_cash.1, statements.annual_cash.0.len()),
+            (&statements.quarter_cash.1, statements.quarter_cash.0.len()),
+            (&metrics.annual_ratios.1, metrics.annual_ratios.0.len()),
+            (&metrics.quarter_ratios.1, metrics.quarter_ratios.0.len()),
+            (&metrics.ttm_ratios.1, metrics.ttm_ratios.0.len()),
+            (
+                &metrics.annual_key_metrics.1,
+                metrics.annual_key_metrics.0.len(),
+            ),
+            (
+                &metrics.quarter_key_metrics.1,
+                metrics.quarter_key_metrics.0.len(),
+            ),
+            (&metrics.ttm_key_metrics.1, metrics.ttm_key_metrics.0.len()),
+        ];
+
+        // Pull times are days, so the lengths tell apart two pulls made on the same day
+        let mut hasher = DefaultHasher::new();
+        stock.ticker.hash(&mut hasher);
+        for (stats, rows) in &pulls {
+            stats.last_pull_time.hash(&mut hasher);
+            stats.last_pull_length.hash(&mut hasher);
+            rows.hash(&mut hasher);
+        }
+        stock.other.profile.len().hash(&mut hasher);
+        stock.other.dcf.len().hash(&mut hasher);
+
+        let last_modified = pulls
+            .iter()
+            .filter_map(|(stats, _)| stats.last_pull_time)
+            .max()
+            .and_then(|day: NaiveDate| day.and_hms_opt(0, 0, 0));
+
+        Self {
+            // Weak, the compression layer may send the same data in a different encoding
+            etag: format!("W/\"{:016x}\"", hasher.finish()),
+            last_modified,
+        }
+    }
+
+    // If-None-Match wins over If-Modified-Since when a client sends both. Last-Modified is the
+    // start of the pull day, so a date on that same day can't rule out a second pull after it
+    // and only a date on a later day answers 304.
+    fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(tags) = headers.get(header::IF_NONE_MATCH) {
+            return tags.to_str().is_ok_and(|tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .any(|tag| tag == "*" || weak_eq(tag, &self.etag))
+            });
+        }
+
+        match (headers.get(header::IF_MODIFIED_SINCE), self.last_modified) {
+            (Some(since), Some(modified)) => since
+                .to_str()
+                .ok()
+                .and_then(|since| NaiveDateTime::parse_from_str(since, HTTP_DATE).ok())
+                .is_some_and(|since| modified.date() < since.date()),
+            _ => false,
+        }
+    }
+
+    // A bodiless 304 when the client's copy is current, otherwise the full response. The body
+    // is only built when it is going to be sent.
+    pub fn respond<R: IntoResponse>(
+        self,
+        headers: &HeaderMap,
+        body: impl FnOnce() -> R,
+    ) -> Response {
+        let mut response = if self.not_modified(headers) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            body().into_response()
+        };
+
+        let response_headers = response.headers_mut();
+
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            response_headers.insert(header::ETAG, etag);
+        }
+
+        if let Some(modified) = self.last_modified {
+            if let Ok(modified) = HeaderValue::from_str(&modified.format(HTTP_DATE).to_string()) {
+                response_headers.insert(header::LAST_MODIFIED, modified);
+            }
+        }
+
+        // Cached copies are fine as long as they are checked with the server first
+        response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        response
+    }
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
@@ -0,0 +1,513 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::response::sse::Event;
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use utoipa::ToSchema;
+
+use crate::backtest::{self, BacktestRequest};
+use crate::helper_structs::ResponseCache;
+use crate::history::{self, ScreenRun};
+use crate::rules::{Evaluation, ScreenDefinition, Universe};
+use crate::screener::Screener;
+use crate::telemetry;
+use crate::{CACHE, JOBS, SCREENER_CACHE};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+// Finished jobs are kept this long for clients still polling them, and no more than MAX_FINISHED
+const FINISHED_TTL_HOURS: i64 = 24;
+const MAX_FINISHED: usize = 100;
+
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Screen(String),
+    Index,
+    Backtest(String),
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Job {
+    pub id: usize,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    // Stocks in the universe and how many of them have been processed so far
+    pub total: usize,
+    pub completed: usize,
+    pub passed: usize,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    cancel: Arc<AtomicBool>,
+    // Stocks that passed so far, replayed to clients that subscribe after the job started
+    #[serde(skip)]
+    found: Vec<PassedStock>,
+    // Dropped once the job finishes, which ends every subscriber's stream
+    #[serde(skip)]
+    events: Option<broadcast::Sender<JobEvent>>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct PassedStock {
+    pub symbol: String,
+    // The left hand value of each criterion, then each ranking factor
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Metric {
+    pub name: String,
+    pub value: Option<f64>,
+}
+
+// The counters of a job, sent on every processed stock instead of the whole job
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct JobProgress {
+    pub id: usize,
+    pub status: JobStatus,
+    pub total: usize,
+    pub completed: usize,
+    pub passed: usize,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    Progress { job: JobProgress },
+    Passed { stock: PassedStock },
+    Finished { job: Job },
+}
+
+impl PassedStock {
+    pub fn new(screen: &ScreenDefinition, evaluation: &Evaluation) -> Self {
+        let criteria = evaluation.criteria.iter().map(|c| Metric {
+            name: c.description.clone(),
+            value: c.left,
+        });
+        let factors = screen
+            .ranking
+            .iter()
+            .flat_map(|ranking| &ranking.factors)
+            .zip(&evaluation.factors)
+            .map(|(factor, value)| Metric {
+                name: factor.name.clone(),
+                value: *value,
+            });
+
+        Self {
+            symbol: evaluation.symbol.clone(),
+            metrics: criteria.chain(factors).collect(),
+        }
+    }
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        self.status != JobStatus::Running
+    }
+
+    fn progress(&self) -> JobProgress {
+        JobProgress {
+            id: self.id,
+            status: self.status,
+            total: self.total,
+            completed: self.completed,
+            passed: self.passed,
+        }
+    }
+
+    fn send(&self, event: JobEvent) {
+        if let Some(events) = &self.events {
+            // Nobody listening is not an error
+            let _ = events.send(event);
+        }
+    }
+}
+
+// Given to the code doing the work so it can report progress and notice cancellation
+#[derive(Debug, Clone)]
+pub struct JobHandle {
+    id: usize,
+    cancel: Arc<AtomicBool>,
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub async fn set_total(&self, total: usize) {
+        self.update(|job| job.total = total).await;
+    }
+
+    pub async fn advance(&self) {
+        self.update(|job| {
+            job.completed += 1;
+            job.send(JobEvent::Progress {
+                job: job.progress(),
+            });
+        })
+        .await;
+    }
+
+    pub async fn pass(&self, screen: &ScreenDefinition, evaluation: &Evaluation) {
+        self.update(|job| {
+            let stock = PassedStock::new(screen, evaluation);
+
+            job.passed += 1;
+            job.found.push(stock.clone());
+            job.send(JobEvent::Passed { stock });
+        })
+        .await;
+    }
+
+    async fn finish(&self, status: JobStatus, error: Option<String>) {
+        self.update(|job| {
+            job.status = status;
+            job.error = error;
+            job.finished_at = Some(Utc::now());
+            telemetry::record_job(job);
+            job.send(JobEvent::Finished { job: job.clone() });
+            job.events = None;
+        })
+        .await;
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = JOBS.lock().await.iter_mut().find(|job| job.id == self.id) {
+            f(job);
+        }
+    }
+}
+
+pub async fn find_job(id: usize) -> Option<Job> {
+    JOBS.lock().await.iter().find(|job| job.id == id).cloned()
+}
+
+pub async fn list() -> Vec<Job> {
+    JOBS.lock().await.clone()
+}
+
+// Everything that happened so far followed by live updates, ending when the job finishes
+pub async fn events(id: usize) -> Option<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    let jobs = JOBS.lock().await;
+    let job = jobs.iter().find(|job| job.id == id)?;
+
+    let history = replay(job);
+    // A finished job has no sender left, the stream ends after the replay
+    let receiver = job.events.as_ref().map(|events| events.subscribe());
+    drop(jobs);
+
+    let (sender, stream) = mpsc::channel(64);
+    tokio::spawn(forward(id, history, receiver, sender));
+
+    Some(ReceiverStream::new(stream).map(|event| Event::default().json_data(event)))
+}
+
+// The stocks that passed so far, then where the job is now
+fn replay(job: &Job) -> Vec<JobEvent> {
+    let mut history: Vec<JobEvent> = job
+        .found
+        .iter()
+        .map(|stock| JobEvent::Passed {
+            stock: stock.clone(),
+        })
+        .collect();
+
+    history.push(if job.is_finished() {
+        JobEvent::Finished { job: job.clone() }
+    } else {
+        JobEvent::Progress {
+            job: job.progress(),
+        }
+    });
+
+    history
+}
+
+// Sends the replay, then live events until the job finishes or the client goes away. A client
+// that falls behind the channel is sent the replay again instead of silently missing stocks.
+async fn forward(
+    id: usize,
+    mut pending: Vec<JobEvent>,
+    mut receiver: Option<broadcast::Receiver<JobEvent>>,
+    sender: mpsc::Sender<JobEvent>,
+) {
+    loop {
+        let finished = pending
+            .iter()
+            .any(|event| matches!(event, JobEvent::Finished { .. }));
+
+        for event in pending {
+            if sender.send(event).await.is_err() {
+                return;
+            }
+        }
+
+        let Some(receiver) = receiver.as_mut().filter(|_| !finished) else {
+            return;
+        };
+
+        pending = match receiver.recv().await {
+            Ok(event) => vec![event],
+            Err(RecvError::Lagged(missed)) => {
+                tracing::debug!(job = id, missed, "job subscriber fell behind, replaying");
+                match find_job(id).await {
+                    Some(job) => replay(&job),
+                    None => return,
+                }
+            }
+            Err(RecvError::Closed) => return,
+        };
+    }
+}
+
+pub async fn cancel(id: usize) -> Option<Job> {
+    let jobs = JOBS.lock().await;
+    let job = jobs.iter().find(|job| job.id == id)?;
+
+    if !job.is_finished() {
+        job.cancel.store(true, Ordering::Relaxed);
+    }
+
+    Some(job.clone())
+}
+
+// The running job for this screen if there is one, so a refresh does not start the screen twice
+pub async fn start_screen(screen: ScreenDefinition) -> Job {
+    let kind = JobKind::Screen(screen.name.clone());
+
+    match register(kind, true).await {
+        Ok((job, handle)) => {
+            spawn(handle.clone(), run_screen(screen, handle));
+            job
+        }
+        Err(running) => running,
+    }
+}
+
+pub async fn start_index() -> Job {
+    match register(JobKind::Index, true).await {
+        Ok((job, handle)) => {
+            spawn(handle.clone(), run_index(handle));
+            job
+        }
+        Err(running) => running,
+    }
+}
+
+// Unlike screens, several backtests of the same screen can run side by side with different dates
+pub async fn start_backtest(screen: ScreenDefinition, request: BacktestRequest) -> Job {
+    let kind = JobKind::Backtest(screen.name.clone());
+
+    match register(kind, false).await {
+        Ok((job, handle)) => {
+            spawn(handle.clone(), run_backtest(screen, request, handle));
+            job
+        }
+        Err(running) => running,
+    }
+}
+
+async fn run_screen(screen: ScreenDefinition, handle: JobHandle) -> Result<(), String> {
+    let started_at = Utc::now();
+    let mut scr = Screener::new();
+    scr.init_screen(&screen.universe).await;
+
+    if scr.stocks_to_screen.is_empty() {
+        return Err(
+            "no stocks in the screen's universe, the symbol list could not be loaded".to_string(),
+        );
+    }
+
+    handle.set_total(scr.stocks_to_screen.len()).await;
+
+    let results = scr.run(&screen, &handle).await;
+
+    if handle.is_cancelled() {
+        return Ok(());
+    }
+
+    // Results missing stocks the provider failed on would look like a screen nothing passed
+    let unfetched: Vec<&Evaluation> = results
+        .evaluations
+        .iter()
+        .filter(|evaluation| evaluation.error.is_some())
+        .collect();
+
+    if let Some(first) = unfetched.first() {
+        return Err(format!(
+            "{} of {} stocks could not be fetched, {}: {}",
+            unfetched.len(),
+            results.evaluations.len(),
+            first.symbol,
+            first.error.as_deref().unwrap_or_default()
+        ));
+    }
+
+    let finished_at = Utc::now();
+    let mut stocks_from_index = Vec::new();
+    let mut constituents = Vec::new();
+    let mut data_as_of = None;
+
+    for i in results.passed {
+        let cache = CACHE.lock().await;
+        let stock = cache.get(i).unwrap();
+
+        stocks_from_index.push(stock.ticker.clone());
+        constituents.push((i, screen.data_stamp(stock)));
+        data_as_of = data_as_of.max(stock.latest_report_date());
+    }
+
+    history::record(ScreenRun {
+        id: 0,
+        screen: screen.name.clone(),
+        definition: screen.clone(),
+        started_at,
+        finished_at,
+        data_as_of,
+        symbols: stocks_from_index.clone(),
+        ranked: results.ranked.clone(),
+    })
+    .await;
+
+    let mut responses = SCREENER_CACHE.lock().await;
+    responses.retain(|res| res.endpoint != screen.name);
+    responses.push(ResponseCache {
+        endpoint: screen.name,
+        data: Json(stocks_from_index),
+        evaluations: results.evaluations,
+        ranked: results.ranked,
+        finished_at,
+        constituents,
+    });
+
+    Ok(())
+}
+
+async fn run_index(handle: JobHandle) -> Result<(), String> {
+    let mut scr = Screener::new();
+    scr.init_screen(&Universe::default()).await;
+
+    if scr.stocks_to_screen.is_empty() {
+        return Err("the symbol list could not be loaded".to_string());
+    }
+
+    handle.set_total(scr.stocks_to_screen.len()).await;
+    scr.index_everything(&handle).await;
+
+    Ok(())
+}
+
+async fn run_backtest(
+    screen: ScreenDefinition,
+    request: BacktestRequest,
+    handle: JobHandle,
+) -> Result<(), String> {
+    let result = backtest::run(&screen, &request, &handle).await;
+
+    if handle.is_cancelled() {
+        return Ok(());
+    }
+
+    backtest::record(result?).await;
+
+    Ok(())
+}
+
+// Runs the work in its own task so a panic is recorded as a failed job instead of one that never ends
+fn spawn(
+    handle: JobHandle,
+    work: impl std::future::Future<Output = Result<(), String>> + Send + 'static,
+) {
+    tokio::spawn(async move {
+        let (status, error) = match tokio::spawn(work).await {
+            Ok(Ok(())) if handle.is_cancelled() => (JobStatus::Cancelled, None),
+            Ok(Ok(())) => (JobStatus::Completed, None),
+            Ok(Err(e)) => (JobStatus::Failed, Some(e)),
+            Err(e) => (JobStatus::Failed, Some(e.to_string())),
+        };
+
+        if let Some(error) = &error {
+            tracing::error!(job = handle.id, error = %error, "job failed");
+        }
+
+        handle.finish(status, error).await;
+    });
+}
+
+// With `exclusive`, a running job of the same kind is handed back instead. Looking for it and
+// registering the new job happen under one lock, so two requests at once can not both start one.
+async fn register(kind: JobKind, exclusive: bool) -> Result<(Job, JobHandle), Job> {
+    let mut jobs = JOBS.lock().await;
+
+    if exclusive {
+        if let Some(job) = jobs
+            .iter()
+            .find(|job| job.kind == kind && !job.is_finished())
+        {
+            return Err(job.clone());
+        }
+    }
+
+    evict_finished(&mut jobs);
+
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    let job = Job {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        kind,
+        status: JobStatus::Running,
+        total: 0,
+        completed: 0,
+        passed: 0,
+        started_at: Utc::now(),
+        finished_at: None,
+        error: None,
+        cancel: cancel.clone(),
+        found: vec![],
+        events: Some(broadcast::channel(1024).0),
+    };
+
+    jobs.push(job.clone());
+
+    let handle = JobHandle { id: job.id, cancel };
+    Ok((job, handle))
+}
+
+// Running jobs are always kept, finished ones until they are old or too many
+fn evict_finished(jobs: &mut Vec<Job>) {
+    let expired = Utc::now() - Duration::hours(FINISHED_TTL_HOURS);
+    jobs.retain(|job| job.finished_at.is_none_or(|finished| finished > expired));
+
+    let mut finished: Vec<(DateTime<Utc>, usize)> = jobs
+        .iter()
+        .filter_map(|job| job.finished_at.map(|finished_at| (finished_at, job.id)))
+        .collect();
+
+    if finished.len() > MAX_FINISHED {
+        finished.sort();
+        let oldest: Vec<usize> = finished[..finished.len() - MAX_FINISHED]
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        jobs.retain(|job| !oldest.contains(&job.id));
+    }
+}
@@ -1,37 +1,88 @@
 use axum::body::{boxed, Body};
-use axum::extract::Path;
-use axum::http::{Response, StatusCode};
+use axum::http::{header, HeaderMap, HeaderValue, Response, StatusCode};
 use axum::Json;
-use axum::{response::IntoResponse, routing::get, Router};
-use cache::get_or_add_stock;
+use axum::response::sse::{KeepAlive, Sse};
+use axum::response::Response as AxumResponse;
+use axum::{
+    middleware,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use cache::get_known_stock;
 use clap::Parser;
 use helper_structs::ResponseCache;
 use once_cell::sync::Lazy;
+use serde::Deserialize;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr};
-use std::ops::Deref;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use stock::Stock;
 use tokio::sync::Mutex;
 use tokio::{fs, signal};
 use tower::{ServiceBuilder, ServiceExt};
+use tower_http::compression::CompressionLayer;
 use tower_http::services::ServeDir;
-use tower_http::trace::TraceLayer;
+use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use utoipa::{IntoParams, OpenApi};
+use utoipa_swagger_ui::{Config, SwaggerUi};
 
-use crate::screener::Screener;
+use crate::backtest::{Backtest, BacktestRequest};
+use crate::batch::BatchRequest;
+use crate::compare::CompareQuery;
+use crate::errors::{validate_symbol, ApiError, ApiJson, ApiPath, ApiQuery};
+use crate::export::{ExportQuery, Format, StockExportQuery};
+use crate::history::ScreenRun;
+use crate::http_cache::Validators;
+use crate::jobs::Job;
+use crate::openapi::ApiDoc;
+use crate::resources::PeriodQuery;
+use crate::rules::ScreenDefinition;
+use crate::search::{SearchQuery, SymbolList};
+use crate::dividends::DividendHistory;
+use crate::helper_structs::TimePeriod;
+use crate::valuation::Valuation;
 
 static CACHE: Lazy<Mutex<Vec<Stock>>> = Lazy::new(|| Mutex::new(cache::state_from_json()));
 static SCREENER_CACHE: Lazy<Mutex<Vec<ResponseCache>>> = Lazy::new(|| Mutex::new(vec![]));
+static SCREENS: Lazy<Mutex<Vec<ScreenDefinition>>> = Lazy::new(|| Mutex::new(vec![]));
+static JOBS: Lazy<Mutex<Vec<Job>>> = Lazy::new(|| Mutex::new(vec![]));
+static CONCURRENCY: AtomicUsize = AtomicUsize::new(8);
+static RUNS: Lazy<Mutex<Vec<ScreenRun>>> = Lazy::new(|| Mutex::new(history::state_from_json()));
+static BACKTESTS: Lazy<Mutex<Vec<Backtest>>> = Lazy::new(|| Mutex::new(vec![]));
+static SYMBOLS: Lazy<Mutex<SymbolList>> = Lazy::new(|| Mutex::new(SymbolList::default()));
 
+mod backtest;
+mod batch;
 mod cache;
+mod compare;
+mod dividends;
+mod errors;
+mod export;
 mod helper_functions;
 mod helper_structs;
+mod history;
+mod http_cache;
+mod jobs;
 mod metrics;
+mod openapi;
 mod other;
+mod prices;
+mod ranking;
+mod resources;
+mod rules;
 mod screener;
+mod search;
+mod screens;
 mod statements;
 mod stock;
+mod telemetry;
 mod utils;
+mod valuation;
 
 // Setup the command line interface with clap.
 #[derive(Parser, Debug)]
@@ -41,6 +92,10 @@ struct Opt {
     #[clap(short = '1', long = "log", default_value = "debug")]
     log_level: String,
 
+    /// set the log output format, text or json
+    #[clap(long = "log-format", default_value = "text")]
+    log_format: String,
+
     /// set the listen addr
     #[clap(short = 'a', long = "addr", default_value = "::1")]
     addr: String,
@@ -52,26 +107,98 @@ struct Opt {
     /// set the directory where static files are to be found
     #[clap(long = "static-dir", default_value = "./dist")]
     static_dir: String,
+
+    /// set the directory where screen definitions are to be found
+    #[clap(long = "screens-dir", default_value = "./screens")]
+    screens_dir: String,
+
+    /// set how many stocks a screen fetches and evaluates at once
+    #[clap(long = "concurrency", default_value = "8")]
+    concurrency: usize,
+
+    /// set how many requests per second are made to the data API
+    #[clap(long = "requests-per-second", default_value = "5")]
+    requests_per_second: u64,
 }
 
 #[tokio::main]
 async fn main() {
-    // let mut scr = Screener::new();
-    // scr.init_screen().await;
-    // scr.index_everything().await;
-
     let opt = Opt::parse();
 
     // Setup logging & RUST_LOG from args
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", format!("{},hyper=info,mio=info", opt.log_level))
     }
-    // enable console logging
-    tracing_subscriber::fmt::init();
+    // enable console logging, spans are logged when they close so their durations show up
+    let logs = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE);
+    match opt.log_format.as_str() {
+        "json" => logs.json().with_current_span(true).with_span_list(true).init(),
+        _ => logs.init(),
+    }
+
+    CONCURRENCY.store(opt.concurrency, Ordering::Relaxed);
+    helper_functions::set_requests_per_second(opt.requests_per_second);
+
+    let screens_dir = PathBuf::from(&opt.screens_dir);
+    *SCREENS.lock().await = screens::load(&screens_dir);
+    screens::restore_results().await;
+    tokio::spawn(screens::watch(screens_dir));
+
+    // The docs page and its assets are served from the binary, the validator is off so the
+    // document isn't sent to swagger.io
+    let docs = SwaggerUi::new("/api/docs")
+        .url("/api/openapi.json", ApiDoc::openapi())
+        .config(Config::new(["/api/openapi.json"]).validator_url("none"));
 
     let app = Router::new()
+        .merge(docs)
+        .route("/api/screeners", get(get_screeners))
         .route("/api/screeners/:name", get(get_screener_results))
+        .route("/api/screeners/:name/export", get(export_screener_results))
+        .route("/api/screeners/:name/run", post(run_screener))
+        .route("/api/screeners/:name/runs", get(get_screener_runs))
+        .route("/api/screeners/:name/runs/diff", get(get_screener_runs_diff))
+        .route("/api/screeners/:name/runs/:id", get(get_screener_run))
+        .route("/api/screeners/:name/scores", get(get_screener_scores))
+        .route(
+            "/api/screeners/:name/explanations",
+            get(get_screener_explanations),
+        )
+        .route(
+            "/api/screeners/:name/explanations/:symbol",
+            get(get_screener_explanation),
+        )
+        .route(
+            "/api/admin/screeners/:name/rerun",
+            post(force_screener_rerun),
+        )
+        .route("/api/jobs", get(get_jobs))
+        .route("/api/jobs/:id", get(get_job))
+        .route("/api/jobs/:id/events", get(get_job_events))
+        .route("/api/jobs/:id/cancel", post(cancel_job))
+        .route("/api/index", post(start_index))
+        .route("/api/backtests", get(get_backtests).post(start_backtest))
+        .route("/api/backtests/:id", get(get_backtest))
+        .route("/api/search", get(get_search))
+        .route("/api/compare", get(get_compare))
+        .route("/api/stocks/batch", post(get_stocks_batch))
         .route("/api/stock/:name", get(get_stock))
+        .route("/api/stock/:name/valuation", get(get_valuation))
+        .route("/api/stock/:name/dividends", get(get_dividends))
+        .route("/api/stock/:name/income", get(get_income))
+        .route("/api/stock/:name/balance", get(get_balance))
+        .route("/api/stock/:name/cashflow", get(get_cash_flow))
+        .route("/api/stock/:name/ratios", get(get_ratios))
+        .route("/api/stock/:name/key-metrics", get(get_key_metrics))
+        .route("/api/stock/:name/profile", get(get_profile))
+        .route("/api/stock/:name/dcf", get(get_dcf))
+        .route("/api/stock/:name/export", get(export_stock))
+        .route("/metrics", get(get_metrics))
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_ready))
+        .route_layer(middleware::from_fn(telemetry::track_http))
         .fallback_service(get(|req| async move {
             match ServeDir::new(&opt.static_dir).oneshot(req).await {
                 Ok(res) => {
@@ -103,14 +230,22 @@ async fn main() {
                     .expect("error response"),
             }
         }))
-        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()));
+        .layer(
+            ServiceBuilder::new()
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(CompressionLayer::new()),
+        );
 
     let sock_addr = SocketAddr::from((
         IpAddr::from_str(opt.addr.as_str()).unwrap_or(IpAddr::V6(Ipv6Addr::LOCALHOST)),
         opt.port,
     ));
 
-    log::info!("listening on http://{}", sock_addr);
+    tracing::info!(addr = %sock_addr, "listening on http://{}", sock_addr);
 
     axum::Server::bind(&sock_addr)
         .serve(app.into_make_service())
@@ -118,46 +253,948 @@ async fn main() {
         .await;
 }
 
-async fn get_screener_results(Path(name): Path<String>) -> impl IntoResponse {
-    if name == "Buffetology" {
-        let mut responses = SCREENER_CACHE.lock().await;
-        let endpoint_in_cache = responses.iter().find(|res| res.endpoint == name);
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Prometheus metrics in the text exposition format", body = String, content_type = "text/plain"),
+    )
+)]
+async fn get_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        telemetry::render().await,
+    )
+}
 
-        match endpoint_in_cache {
-            Some(res) => {
-                println!("USING RESPONSE CACHE...");
-                return res.data.clone();
-            }
-            None => {
-                let mut scr = Screener::new();
-                scr.init_screen().await;
-                let buffetology_stocks = scr.buffetology_screener().await;
-                let mut stocks_from_index = Vec::new();
-
-                for i in buffetology_stocks {
-                    stocks_from_index.push(CACHE.lock().await.get(i).unwrap().to_owned().ticker);
-                }
+// The process is up and answering
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "The server is up", body = String, content_type = "text/plain"),
+    )
+)]
+async fn get_health() -> impl IntoResponse {
+    "ok"
+}
 
-                println!("I GOT HERE!");
+// Able to do useful work: without an api key every data request fails
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Ready, with the checks behind it", body = Object),
+        (status = 503, description = "No api key is configured, with the checks behind it", body = Object),
+    )
+)]
+async fn get_ready() -> impl IntoResponse {
+    let api_key = !utils::api_key().is_empty();
+    let screens = SCREENS.lock().await.len();
+    let cached_stocks = CACHE.lock().await.len();
 
-                responses.push(ResponseCache {
-                    endpoint: name,
-                    data: Json(stocks_from_index.clone()),
-                });
+    let status = if api_key {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
 
-                return Json(stocks_from_index);
+    (
+        status,
+        Json(serde_json::json!({
+            "ready": api_key,
+            "checks": {
+                "api_key": api_key,
+                "screens": screens,
+                "cached_stocks": cached_stocks,
             }
+        })),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/screeners",
+    tag = "screens",
+    responses(
+        (status = 200, description = "Every loaded screen", body = [ScreenSummary]),
+    )
+)]
+async fn get_screeners() -> impl IntoResponse {
+    Json(screens::summaries().await)
+}
+
+async fn find_screen(name: &str) -> Result<ScreenDefinition, ApiError> {
+    screens::find_screen(name)
+        .await
+        .ok_or_else(|| ApiError::unknown_screen(name))
+}
+
+// Cached results when the screen has already run, otherwise the screen is started (or the run
+// already in progress is found) as a background job to poll at /api/jobs/:id
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen")),
+    responses(
+        (status = 200, description = "Symbols that passed, in the screen's order", body = [String]),
+        (status = 202, description = "The screen is running, follow the job in the Location header", body = Job),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn get_screener_results(ApiPath(name): ApiPath<String>) -> Result<AxumResponse, ApiError> {
+    let screen = find_screen(&name).await?;
+
+    screens::invalidate_stale().await;
+
+    if let Some(res) = SCREENER_CACHE
+        .lock()
+        .await
+        .iter()
+        .find(|res| res.endpoint == name)
+    {
+        return Ok(res.data.clone().into_response());
+    }
+
+    Ok(job_accepted(jobs::start_screen(screen).await))
+}
+
+// Same as the results, but as a spreadsheet with the value behind every criterion and factor
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/export",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen"), ExportQuery),
+    responses(
+        (status = 200, description = "The stocks that passed as a csv or xlsx file", content_type = "text/csv"),
+        (status = 202, description = "The screen is running, follow the job in the Location header", body = Job),
+        (status = 400, description = "Unknown format", body = ErrorResponse),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn export_screener_results(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<ExportQuery>,
+) -> Result<AxumResponse, ApiError> {
+    let format = Format::parse(query.format.as_deref()).map_err(ApiError::bad_request)?;
+    let screen = find_screen(&name).await?;
+
+    screens::invalidate_stale().await;
+
+    if let Some(res) = SCREENER_CACHE
+        .lock()
+        .await
+        .iter()
+        .find(|res| res.endpoint == name)
+    {
+        let table = export::screen_table(&screen, res);
+        return export::download(format, &screen.name, &[table]);
+    }
+
+    Ok(job_accepted(jobs::start_screen(screen).await))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/screeners/{name}/run",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen")),
+    responses(
+        (status = 202, description = "The job running the screen", body = Job),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn run_screener(ApiPath(name): ApiPath<String>) -> Result<AxumResponse, ApiError> {
+    let screen = find_screen(&name).await?;
+    Ok(job_accepted(jobs::start_screen(screen).await))
+}
+
+// Throws away the cached results, even fresh ones, and runs the screen again
+#[utoipa::path(
+    post,
+    path = "/api/admin/screeners/{name}/rerun",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen")),
+    responses(
+        (status = 202, description = "The job running the screen", body = Job),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn force_screener_rerun(ApiPath(name): ApiPath<String>) -> Result<AxumResponse, ApiError> {
+    let screen = find_screen(&name).await?;
+    screens::invalidate(&name).await;
+
+    Ok(job_accepted(jobs::start_screen(screen).await))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "jobs",
+    responses(
+        (status = 200, description = "Every job since the server started", body = [Job]),
+    )
+)]
+async fn get_jobs() -> impl IntoResponse {
+    Json(jobs::list().await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}",
+    tag = "jobs",
+    params(("id" = usize, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "The job", body = Job),
+        (status = 404, description = "Unknown job", body = ErrorResponse),
+    )
+)]
+async fn get_job(ApiPath(id): ApiPath<usize>) -> Result<Json<Job>, ApiError> {
+    jobs::find_job(id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("job", id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}/events",
+    tag = "jobs",
+    params(("id" = usize, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "Server-sent events, each one a JobEvent", content_type = "text/event-stream", body = JobEvent),
+        (status = 404, description = "Unknown job", body = ErrorResponse),
+    )
+)]
+async fn get_job_events(ApiPath(id): ApiPath<usize>) -> Result<AxumResponse, ApiError> {
+    match jobs::events(id).await {
+        Some(events) => Ok(Sse::new(events)
+            .keep_alive(KeepAlive::default())
+            .into_response()),
+        None => Err(ApiError::not_found("job", id)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/jobs/{id}/cancel",
+    tag = "jobs",
+    params(("id" = usize, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "The job, stopping", body = Job),
+        (status = 404, description = "Unknown job", body = ErrorResponse),
+    )
+)]
+async fn cancel_job(ApiPath(id): ApiPath<usize>) -> Result<Json<Job>, ApiError> {
+    jobs::cancel(id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("job", id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/index",
+    tag = "jobs",
+    responses(
+        (status = 202, description = "The job fetching every stock", body = Job),
+    )
+)]
+async fn start_index() -> AxumResponse {
+    job_accepted(jobs::start_index().await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/backtests",
+    tag = "backtests",
+    request_body = BacktestRequest,
+    responses(
+        (status = 202, description = "The job running the backtest", body = Job),
+        (status = 400, description = "Invalid dates or benchmark symbol, or a screen reading TTM ratios", body = ErrorResponse),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+        (status = 422, description = "Malformed request", body = ErrorResponse),
+    )
+)]
+async fn start_backtest(
+    ApiJson(mut request): ApiJson<BacktestRequest>,
+) -> Result<AxumResponse, ApiError> {
+    let screen = find_screen(&request.screen).await?;
+    request.validate().map_err(ApiError::bad_request)?;
+    backtest::check_screen(&screen).map_err(ApiError::bad_request)?;
+    request.benchmark = validate_symbol(&request.benchmark)?;
+
+    Ok(job_accepted(jobs::start_backtest(screen, request).await))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/backtests",
+    tag = "backtests",
+    responses(
+        (status = 200, description = "Every finished backtest", body = [BacktestSummary]),
+    )
+)]
+async fn get_backtests() -> impl IntoResponse {
+    Json(backtest::list().await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/backtests/{id}",
+    tag = "backtests",
+    params(("id" = usize, Path, description = "Backtest id")),
+    responses(
+        (status = 200, description = "The backtest", body = Backtest),
+        (status = 404, description = "Unknown backtest", body = ErrorResponse),
+    )
+)]
+async fn get_backtest(ApiPath(id): ApiPath<usize>) -> Result<Json<Backtest>, ApiError> {
+    backtest::find_backtest(id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("backtest", id))
+}
+
+fn job_accepted(job: Job) -> AxumResponse {
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/api/jobs/{}", job.id))],
+        Json(job),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExplanationQuery {
+    /// passed, failed or all (the default)
+    outcome: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/explanations",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen"), ExplanationQuery),
+    responses(
+        (status = 200, description = "Why each stock passed or failed the last run", body = [Evaluation]),
+        (status = 400, description = "Unknown outcome", body = ErrorResponse),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn get_screener_explanations(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<ExplanationQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    find_screen(&name).await?;
+    screens::invalidate_stale().await;
+
+    let responses = SCREENER_CACHE.lock().await;
+    let evaluations = match responses.iter().find(|res| res.endpoint == name) {
+        Some(res) => res.evaluations.clone(),
+        None => vec![],
+    };
+
+    let evaluations = match query.outcome.as_deref() {
+        Some("passed") => evaluations.into_iter().filter(|e| e.passed).collect(),
+        Some("failed") => evaluations.into_iter().filter(|e| !e.passed).collect(),
+        Some(other) if other != "all" => {
+            return Err(ApiError::bad_request(format!(
+                "unknown outcome {}, expected passed, failed or all",
+                other
+            )))
         }
+        _ => evaluations,
+    };
+
+    Ok(Json(evaluations))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/scores",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen")),
+    responses(
+        (status = 200, description = "Scores of a ranking screen's last run, best first", body = [RankedStock]),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn get_screener_scores(
+    ApiPath(name): ApiPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    find_screen(&name).await?;
+    screens::invalidate_stale().await;
+
+    if let Some(res) = SCREENER_CACHE
+        .lock()
+        .await
+        .iter()
+        .find(|res| res.endpoint == name)
+    {
+        return Ok(Json(res.ranked.clone()));
     }
 
-    Json(vec![])
+    Ok(Json(vec![]))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/runs",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen")),
+    responses(
+        (status = 200, description = "Past runs, oldest first", body = [RunSummary]),
+        (status = 404, description = "Unknown screen", body = ErrorResponse),
+    )
+)]
+async fn get_screener_runs(ApiPath(name): ApiPath<String>) -> Result<impl IntoResponse, ApiError> {
+    find_screen(&name).await?;
+    Ok(Json(history::runs(&name).await))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/runs/{id}",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen"), ("id" = usize, Path, description = "Run id")),
+    responses(
+        (status = 200, description = "The run", body = ScreenRun),
+        (status = 404, description = "Unknown run", body = ErrorResponse),
+    )
+)]
+async fn get_screener_run(
+    ApiPath((name, id)): ApiPath<(String, usize)>,
+) -> Result<Json<ScreenRun>, ApiError> {
+    history::find_run(&name, id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("run", id))
 }
 
-async fn get_stock(Path(name): Path<String>) -> impl IntoResponse {
-    let mut stock = get_or_add_stock(name.clone()).await;
-    stock.get_all().await;
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DiffQuery {
+    /// Run id, the run before `to` when not given
+    from: Option<usize>,
+    /// Run id, the latest run when not given
+    to: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/runs/diff",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen"), DiffQuery),
+    responses(
+        (status = 200, description = "Stocks that entered, left and stayed between two runs", body = RunDiff),
+        (status = 404, description = "Unknown screen or fewer than two runs", body = ErrorResponse),
+    )
+)]
+async fn get_screener_runs_diff(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<DiffQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    find_screen(&name).await?;
+
+    history::diff(&name, query.from, query.to)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "there are not two runs of the screen to compare",
+            )
+        })
+}
 
-    Json(vec![stock.deref().to_owned()])
+#[utoipa::path(
+    get,
+    path = "/api/screeners/{name}/explanations/{symbol}",
+    tag = "screens",
+    params(("name" = String, Path, description = "Name of the screen"), ("symbol" = String, Path, description = "Ticker symbol")),
+    responses(
+        (status = 200, description = "The stock evaluated against the screen now", body = [Evaluation]),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown screen or symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_screener_explanation(
+    ApiPath((name, symbol)): ApiPath<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let screen = find_screen(&name).await?;
+    let mut stock = known_stock(&symbol).await?;
+
+    // A failing provider is reported rather than explained as missing data
+    let fetched = screen.fetch(&mut stock).await;
+    let stock = cache::store_stock(stock).await;
+    fetched?;
+
+    Ok(Json(vec![screen.evaluate_offline(&stock)]))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/export",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL"), StockExportQuery),
+    responses(
+        (status = 200, description = "Statements and metrics as a csv or xlsx file, one section or worksheet each", content_type = "text/csv"),
+        (status = 400, description = "Invalid ticker symbol, format, sections or period", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol or no data for it", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn export_stock(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<StockExportQuery>,
+) -> Result<AxumResponse, ApiError> {
+    let format = Format::parse(query.format.as_deref()).map_err(ApiError::bad_request)?;
+    let sections =
+        export::parse_sections(query.sections.as_deref()).map_err(ApiError::bad_request)?;
+    let period = PeriodQuery {
+        period: query.period,
+        limit: query.limit,
+    };
+    let period_name = period.period.clone().unwrap_or("annual".to_string());
+    let period = period.time_period().map_err(ApiError::bad_request)?;
+
+    let mut stock = known_stock(&name).await?;
+    let tables = export::stock_tables(&mut stock, &sections, period).await;
+    let stock = cache::store_stock(stock).await;
+    let tables = tables?;
+
+    if tables.is_empty() {
+        return Err(ApiError::no_data(&stock.ticker, "statements"));
+    }
+
+    export::download(format, &format!("{}-{}", stock.ticker, period_name), &tables)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "stocks",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching symbols, best match first", body = [SearchResult]),
+        (status = 400, description = "Empty query", body = ErrorResponse),
+    )
+)]
+async fn get_search(
+    ApiQuery(query): ApiQuery<SearchQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::bad_request(
+            "q has to contain part of a ticker or name",
+        ));
+    }
+
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+
+    Ok(Json(search::search(&query.q, limit).await))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compare",
+    tag = "stocks",
+    params(CompareQuery),
+    responses(
+        (status = 200, description = "The fields of every stock lined up by period", body = StockComparison),
+        (status = 400, description = "Invalid symbols, fields or period", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_compare(
+    ApiQuery(query): ApiQuery<CompareQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let symbols = compare::parse_symbols(&query.symbols).map_err(ApiError::bad_request)?;
+    let fields = compare::parse_fields(&query.fields).map_err(ApiError::bad_request)?;
+    let period = PeriodQuery {
+        period: query.period,
+        limit: query.limit,
+    }
+    .time_period()
+    .map_err(ApiError::bad_request)?;
+
+    let mut points = vec![];
+    for symbol in &symbols {
+        let mut stock = known_stock(symbol).await?;
+        let collected = compare::collect(&mut stock, &fields, &period).await;
+        cache::store_stock(stock).await;
+        points.push(collected?);
+    }
+
+    compare::align(&period, fields, points)
+        .map(Json)
+        .map_err(ApiError::bad_request)
+}
+
+// Always 200 when the request itself is valid, each symbol carries its own status and error
+#[utoipa::path(
+    post,
+    path = "/api/stocks/batch",
+    tag = "stocks",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "One result per symbol, in the order asked for", body = [BatchResult]),
+        (status = 400, description = "No symbols, too many or an invalid period", body = ErrorResponse),
+        (status = 422, description = "Malformed request", body = ErrorResponse),
+    )
+)]
+async fn get_stocks_batch(
+    ApiJson(request): ApiJson<BatchRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let symbols = request.symbols().map_err(ApiError::bad_request)?;
+    let sections = request.sections();
+    let period = PeriodQuery {
+        period: request.period,
+        limit: request.limit,
+    }
+    .time_period()
+    .map_err(ApiError::bad_request)?;
+
+    Ok(Json(batch::fetch(symbols, sections, period).await))
+}
+
+// A copy of the cached stock, whatever a handler fetches into it goes back with store_stock
+async fn known_stock(symbol: &str) -> Result<Stock, ApiError> {
+    get_known_stock(validate_symbol(symbol)?).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL")),
+    responses(
+        (status = 200, description = "Everything known about the stock", body = [Stock], headers(
+            ("x-missing-sections" = String, description = "Sections other than the statements the provider failed on, comma separated")
+        )),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_stock(
+    ApiPath(name): ApiPath<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut stock = known_stock(&name).await?;
+    let fetched = stock.get_all().await;
+    let stock = cache::store_stock(stock).await;
+    let missing = fetched?;
+
+    let mut response =
+        Validators::for_stock(&stock).respond(&headers, || Json(vec![stock.clone()]));
+
+    // The stock is still served when only sections beyond the statements failed
+    if !missing.is_empty() {
+        if let Ok(missing) = HeaderValue::from_str(&missing.join(", ")) {
+            response.headers_mut().insert("x-missing-sections", missing);
+        }
+    }
+
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/valuation",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL")),
+    responses(
+        (status = 200, description = "Fair value estimates", body = Valuation),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_valuation(ApiPath(name): ApiPath<String>) -> Result<impl IntoResponse, ApiError> {
+    let mut stock = known_stock(&name).await?;
+    let fetched = Valuation::fetch(&mut stock).await;
+    let stock = cache::store_stock(stock).await;
+    fetched?;
+
+    Ok(Json(Valuation::from_stock(&stock)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/dividends",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL")),
+    responses(
+        (status = 200, description = "Eleven years of dividends, enough to measure ten years of growth", body = DividendHistory),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_dividends(
+    ApiPath(name): ApiPath<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut stock = known_stock(&name).await?;
+    let fetched = DividendHistory::fetch(&mut stock, TimePeriod::Annual(dividends::YEARS)).await;
+    let stock = cache::store_stock(stock).await;
+    fetched?;
+
+    Ok(Validators::for_stock(&stock).respond(&headers, || {
+        Json(DividendHistory::from_stock(&stock))
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/income",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL"), PeriodQuery),
+    responses(
+        (status = 200, description = "Income statements, newest first", body = IncomeRows),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_income(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<PeriodQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let period = query.time_period().map_err(ApiError::bad_request)?;
+    let mut stock = known_stock(&name).await?;
+
+    let rows = resources::income(&mut stock, period).await;
+    let stock = cache::store_stock(stock).await;
+    let rows = rows?;
+
+    Ok(Validators::for_stock(&stock).respond(&headers, || Json(rows)))
+}
+
+// Balance sheets and cash flows are only reported per period, there is no TTM version
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/balance",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL"), PeriodQuery),
+    responses(
+        (status = 200, description = "Balance sheets, newest first, there is no ttm", body = BalanceRows),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_balance(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<PeriodQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let period = match query.time_period().map_err(ApiError::bad_request)? {
+        TimePeriod::TTM() => return Err(ApiError::bad_request("there is no ttm balance sheet")),
+        period => period,
+    };
+    let mut stock = known_stock(&name).await?;
+
+    let rows = resources::balance(&mut stock, period).await;
+    let stock = cache::store_stock(stock).await;
+    let rows = rows?;
+
+    Ok(Validators::for_stock(&stock).respond(&headers, || Json(rows)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/cashflow",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL"), PeriodQuery),
+    responses(
+        (status = 200, description = "Cash flow statements, newest first, there is no ttm", body = CashFlowRows),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_cash_flow(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<PeriodQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let period = match query.time_period().map_err(ApiError::bad_request)? {
+        TimePeriod::TTM() => {
+            return Err(ApiError::bad_request("there is no ttm cash flow statement"))
+        }
+        period => period,
+    };
+    let mut stock = known_stock(&name).await?;
+
+    let rows = resources::cash_flow(&mut stock, period).await;
+    let stock = cache::store_stock(stock).await;
+    let rows = rows?;
+
+    Ok(Validators::for_stock(&stock).respond(&headers, || Json(rows)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/ratios",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL"), PeriodQuery),
+    responses(
+        (status = 200, description = "Ratios, newest first, the ttm period has RatiosTTM rows", body = RatioRows),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_ratios(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<PeriodQuery>,
+    headers: HeaderMap,
+) -> Result<AxumResponse, ApiError> {
+    let period = query.time_period().map_err(ApiError::bad_request)?;
+    let mut stock = known_stock(&name).await?;
+
+    Ok(match period {
+        TimePeriod::TTM() => {
+            let rows = resources::ratios_ttm(&mut stock).await;
+            let stock = cache::store_stock(stock).await;
+            let rows = rows?;
+            Validators::for_stock(&stock).respond(&headers, || Json(rows))
+        }
+        period => {
+            let rows = resources::ratios(&mut stock, period).await;
+            let stock = cache::store_stock(stock).await;
+            let rows = rows?;
+            Validators::for_stock(&stock).respond(&headers, || Json(rows))
+        }
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/key-metrics",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL"), PeriodQuery),
+    responses(
+        (status = 200, description = "Key metrics, newest first, the ttm period has KeyMetricsTTM rows", body = KeyMetricRows),
+        (status = 304, description = "Unchanged since the ETag or date the client sent"),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_key_metrics(
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<PeriodQuery>,
+    headers: HeaderMap,
+) -> Result<AxumResponse, ApiError> {
+    let period = query.time_period().map_err(ApiError::bad_request)?;
+    let mut stock = known_stock(&name).await?;
+
+    Ok(match period {
+        TimePeriod::TTM() => {
+            let rows = resources::key_metrics_ttm(&mut stock).await;
+            let stock = cache::store_stock(stock).await;
+            let rows = rows?;
+            Validators::for_stock(&stock).respond(&headers, || Json(rows))
+        }
+        period => {
+            let rows = resources::key_metrics(&mut stock, period).await;
+            let stock = cache::store_stock(stock).await;
+            let rows = rows?;
+            Validators::for_stock(&stock).respond(&headers, || Json(rows))
+        }
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/profile",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL")),
+    responses(
+        (status = 200, description = "Company profile", body = Profile),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_profile(ApiPath(name): ApiPath<String>) -> Result<impl IntoResponse, ApiError> {
+    let mut stock = known_stock(&name).await?;
+
+    let profile = resources::profile(&mut stock).await;
+    let stock = cache::store_stock(stock).await;
+
+    match profile? {
+        Some(profile) => Ok(Json(profile)),
+        None => Err(ApiError::no_data(&stock.ticker, "profile")),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock/{name}/dcf",
+    tag = "stocks",
+    params(("name" = String, Path, description = "Ticker symbol, e.g. AAPL")),
+    responses(
+        (status = 200, description = "Levered discounted cash flow valuation", body = AdvancedLeveredDiscountedCashFlow),
+        (status = 400, description = "Invalid ticker symbol", body = ErrorResponse),
+        (status = 404, description = "Unknown symbol", body = ErrorResponse),
+        (status = 429, description = "Rate limited by the data provider", body = ErrorResponse),
+        (status = 502, description = "The data provider failed", body = ErrorResponse),
+        (status = 503, description = "The data provider is unreachable", body = ErrorResponse),
+    )
+)]
+async fn get_dcf(ApiPath(name): ApiPath<String>) -> Result<impl IntoResponse, ApiError> {
+    let mut stock = known_stock(&name).await?;
+
+    let dcf = resources::dcf(&mut stock).await;
+    let stock = cache::store_stock(stock).await;
+
+    match dcf? {
+        Some(dcf) => Ok(Json(dcf)),
+        None => Err(ApiError::no_data(&stock.ticker, "discounted cash flow")),
+    }
 }
 
 async fn shutdown() {
@@ -184,5 +1221,5 @@ async fn shutdown() {
     }
 
     cache::save().await;
-    println!("signal received, starting graceful shutdown");
+    tracing::info!("signal received, starting graceful shutdown");
 }
@@ -2,22 +2,46 @@ use core::fmt::Debug;
 use serde::de::DeserializeOwned;
 use std::any::TypeId;
 
-use crate::helper_functions::api;
-use crate::helper_structs::{FetchStats, KeyMetrics, KeyMetricsTTM, Ratios, RatiosTTM, TimePeriod};
+use crate::helper_functions::api_result;
+use crate::helper_structs::{fetched_rows, keep_newer, FetchStats, KeyMetrics, KeyMetricsTTM, Ratios, RatiosTTM, TimePeriod};
 use crate::utils::{needs_update_based_on_time, update_pull_stats};
 
 use serde::{Deserialize, Serialize};
+use utoipa::openapi::schema::Array;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Metrics {
+    #[schema(schema_with = ratio_rows)]
     pub annual_ratios: (Vec<Ratios>, FetchStats),
+    #[schema(schema_with = ratio_rows)]
     pub quarter_ratios: (Vec<Ratios>, FetchStats),
+    #[schema(schema_with = ratio_ttm_rows)]
     pub ttm_ratios: (Vec<RatiosTTM>, FetchStats),
+    #[schema(schema_with = key_metric_rows)]
     pub annual_key_metrics: (Vec<KeyMetrics>, FetchStats),
+    #[schema(schema_with = key_metric_rows)]
     pub quarter_key_metrics: (Vec<KeyMetrics>, FetchStats),
+    #[schema(schema_with = key_metric_ttm_rows)]
     pub ttm_key_metrics: (Vec<KeyMetricsTTM>, FetchStats),
 }
 
+fn ratio_rows() -> Array {
+    fetched_rows("Ratios")
+}
+
+fn ratio_ttm_rows() -> Array {
+    fetched_rows("RatiosTTM")
+}
+
+fn key_metric_rows() -> Array {
+    fetched_rows("KeyMetrics")
+}
+
+fn key_metric_ttm_rows() -> Array {
+    fetched_rows("KeyMetricsTTM")
+}
+
 impl Metrics {
     pub fn new() -> Self {
         Self {
@@ -30,7 +54,16 @@ impl Metrics {
         }
     }
 
-    pub async fn fetch<T>(&mut self, period: TimePeriod, symbol: &String)
+    pub fn merge(&mut self, fetched: Metrics) {
+        keep_newer(&mut self.annual_ratios, fetched.annual_ratios);
+        keep_newer(&mut self.quarter_ratios, fetched.quarter_ratios);
+        keep_newer(&mut self.ttm_ratios, fetched.ttm_ratios);
+        keep_newer(&mut self.annual_key_metrics, fetched.annual_key_metrics);
+        keep_newer(&mut self.quarter_key_metrics, fetched.quarter_key_metrics);
+        keep_newer(&mut self.ttm_key_metrics, fetched.ttm_key_metrics);
+    }
+
+    pub async fn fetch<T>(&mut self, period: TimePeriod, symbol: &String) -> Result<(), reqwest::Error>
     where
         T: DeserializeOwned + Debug + 'static,
     {
@@ -45,7 +78,7 @@ impl Metrics {
                         || should_update
                     {
                         self.annual_ratios =
-                            (api::<Ratios>(&period, &symbol, "".to_string()).await, stats);
+                            (api_result::<Ratios>(&period, &symbol, "".to_string()).await?, stats);
                     }
                     // else {
                     //     println!("USED CACHE FOR RATIOS - {}!", &symbol);
@@ -57,7 +90,7 @@ impl Metrics {
                         || should_update
                     {
                         self.quarter_ratios =
-                            (api::<Ratios>(&period, &symbol, "".to_string()).await, stats);
+                            (api_result::<Ratios>(&period, &symbol, "".to_string()).await?, stats);
                     }
                     // else {
                     //     println!("USED CACHE FOR RATIOS (QTR) - {}!", &symbol);
@@ -73,7 +106,7 @@ impl Metrics {
                         || should_update
                     {
                         self.annual_key_metrics = (
-                            api::<KeyMetrics>(&period, &symbol, "".to_string()).await,
+                            api_result::<KeyMetrics>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -87,7 +120,7 @@ impl Metrics {
                         || should_update
                     {
                         self.quarter_key_metrics = (
-                            api::<KeyMetrics>(&period, &symbol, "".to_string()).await,
+                            api_result::<KeyMetrics>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -102,7 +135,7 @@ impl Metrics {
                 || should_update
             {
                 self.ttm_ratios = (
-                    api::<RatiosTTM>(&period, &symbol, "".to_string()).await,
+                    api_result::<RatiosTTM>(&period, &symbol, "".to_string()).await?,
                     stats,
                 );
             }
@@ -115,7 +148,7 @@ impl Metrics {
                 || should_update
             {
                 self.ttm_key_metrics = (
-                    api::<KeyMetricsTTM>(&period, &symbol, "".to_string()).await,
+                    api_result::<KeyMetricsTTM>(&period, &symbol, "".to_string()).await?,
                     stats,
                 );
             }
@@ -123,5 +156,102 @@ impl Metrics {
             //     println!("USED CACHE FOR KEY_METRICS_TTM - {}!", &symbol);
             // }
         }
+
+        Ok(())
     }
 }
+
+// TTM key metrics as they stood at the newest of four quarters, None with fewer quarters. Balance
+// sheet and market values are the newest quarter's, per share flows, yields and returns add up,
+// price and value multiples of a flow are taken over the summed flow, and ratios of two flows
+// and days are averaged.
+pub fn ttm_key_metrics(quarters: &[KeyMetrics]) -> Option<KeyMetricsTTM> {
+    let quarters = quarters.get(..4)?;
+    let newest = &quarters[0];
+    let values = |value: fn(&KeyMetrics) -> Option<f64>| -> Option<Vec<f64>> {
+        quarters.iter().map(value).collect()
+    };
+    let sum = |value| values(value).map(|v| v.iter().sum::<f64>());
+    let mean = |value| sum(value).map(|total| total / 4.0);
+    // Price over a quarter's flow is one over its yield, the yields add up to the year's
+    let multiple = |value| {
+        let v = values(value)?;
+        if v.contains(&0.0) {
+            return None;
+        }
+
+        let yearly: f64 = v.iter().map(|m| 1.0 / m).sum();
+        (yearly != 0.0).then(|| 1.0 / yearly)
+    };
+
+    let net_income_per_share = sum(|q| q.net_income_per_share);
+    let graham_number = net_income_per_share
+        .zip(newest.book_value_per_share)
+        .map(|(eps, book)| 22.5 * eps * book)
+        .filter(|product| *product > 0.0)
+        .map(f64::sqrt);
+
+    Some(KeyMetricsTTM {
+        revenue_per_share_TTM: sum(|q| q.revenue_per_share),
+        net_income_per_share_TTM: net_income_per_share,
+        operating_cash_flow_per_share_TTM: sum(|q| q.operating_cash_flow_per_share),
+        free_cash_flow_per_share_TTM: sum(|q| q.free_cash_flow_per_share),
+        cash_per_share_TTM: newest.cash_per_share,
+        book_value_per_share_TTM: newest.book_value_per_share,
+        tangible_book_value_per_share_TTM: newest.tangible_book_value_per_share,
+        shareholders_equity_per_share_TTM: newest.shareholders_equity_per_share,
+        interest_debt_per_share_TTM: newest.interest_debt_per_share,
+        market_cap_TTM: newest.market_cap,
+        enterprise_value_TTM: newest.enterprise_value,
+        pe_ratio_TTM: multiple(|q| q.pe_ratio),
+        price_to_sales_ratio_TTM: multiple(|q| q.price_to_sales_ratio),
+        pocfratio_TTM: multiple(|q| q.pocfratio),
+        pfcf_ratio_TTM: multiple(|q| q.pfcf_ratio),
+        pb_ratio_TTM: newest.pb_ratio,
+        ptb_ratio_TTM: newest.ptb_ratio,
+        ev_to_sales_TTM: multiple(|q| q.ev_to_sales),
+        enterprise_value_over_EBITDATTM: multiple(|q| q.enterprise_value_over_EBITDA),
+        ev_to_operating_cash_flow_TTM: multiple(|q| q.ev_to_operating_cash_flow),
+        ev_to_free_cash_flow_TTM: multiple(|q| q.ev_to_free_cash_flow),
+        earnings_yield_TTM: sum(|q| q.earnings_yield),
+        free_cash_flow_yield_TTM: sum(|q| q.free_cash_flow_yield),
+        debt_to_equity_TTM: newest.debt_to_equity,
+        debt_to_assets_TTM: newest.debt_to_assets,
+        net_debt_to_EBITDATTM: multiple(|q| q.net_debt_to_EBITDA),
+        current_ratio_TTM: newest.current_ratio,
+        interest_coverage_TTM: mean(|q| q.interest_coverage),
+        income_quality_TTM: mean(|q| q.income_quality),
+        dividend_yield_TTM: sum(|q| q.dividend_yield),
+        payout_ratio_TTM: mean(|q| q.payout_ratio),
+        sales_general_and_administrative_to_revenue_TTM: mean(|q| {
+            q.sales_general_and_administrative_to_revenue
+        }),
+        research_and_developement_to_revenue_TTM: mean(|q| q.research_and_ddevelopement_to_revenue),
+        intangibles_to_total_assets_TTM: newest.intangibles_to_total_assets,
+        capex_to_operating_cash_flow_TTM: mean(|q| q.capex_to_operating_cash_flow),
+        capex_to_revenue_TTM: mean(|q| q.capex_to_revenue),
+        capex_to_depreciation_TTM: mean(|q| q.capex_to_depreciation),
+        stock_based_compensation_to_revenue_TTM: mean(|q| q.stock_based_compensation_to_revenue),
+        graham_number_TTM: graham_number,
+        roic_TTM: sum(|q| q.roic),
+        return_on_tangible_assets_TTM: sum(|q| q.return_on_tangible_assets),
+        graham_net_net_TTM: newest.graham_net_net,
+        working_capital_TTM: newest.working_capital,
+        tangible_asset_value_TTM: newest.tangible_asset_value,
+        net_current_asset_value_TTM: newest.net_current_asset_value,
+        invested_capital_TTM: newest.invested_capital,
+        average_receivables_TTM: newest.average_receivables,
+        average_payables_TTM: newest.average_payables,
+        average_inventory_TTM: newest.average_inventory,
+        days_sales_outstanding_TTM: mean(|q| q.days_sales_outstanding),
+        days_payables_outstanding_TTM: mean(|q| q.days_payables_outstanding),
+        days_of_inventory_on_hand_TTM: mean(|q| q.days_of_inventory_on_hand),
+        receivables_turnover_TTM: sum(|q| q.receivables_turnover),
+        payables_turnover_TTM: sum(|q| q.payables_turnover),
+        inventory_turnover_TTM: sum(|q| q.inventory_turnover),
+        roe_TTM: sum(|q| q.roe),
+        capex_per_share_TTM: sum(|q| q.capex_per_share),
+        dividend_per_share_TTM: None,
+        debt_to_market_cap_TTM: None,
+    })
+}
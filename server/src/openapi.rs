@@ -0,0 +1,142 @@
+use utoipa::OpenApi;
+
+use crate::backtest::{Backtest, BacktestPeriod, BacktestRequest, BacktestSummary};
+use crate::batch::{BatchRequest, BatchResult};
+use crate::compare::{CompareField, FieldSeries, StockComparison, StockSeries};
+use crate::dividends::{DividendHistory, DividendYear};
+use crate::errors::{ApiError, ErrorResponse};
+use crate::helper_structs::{
+    AdvancedLeveredDiscountedCashFlow, BalanceSheetStatement, CashFlowStatement, FetchStats,
+    IncomeStatement, KeyMetrics, KeyMetricsTTM, Profile, Ratios, RatiosTTM,
+};
+use crate::history::{RunDiff, RunSummary, ScreenRun};
+use crate::jobs::{Job, JobEvent, JobKind, JobProgress, JobStatus, Metric, PassedStock};
+use crate::metrics::Metrics;
+use crate::other::Other;
+use crate::ranking::{Factor, FactorScore, RankedStock, Ranking};
+use crate::resources::{BalanceRows, CashFlowRows, IncomeRows, KeyMetricRows, RatioRows};
+use crate::rules::{
+    Aggregation, Comparison, CriterionResult, Evaluation, Expr, Field, Outcome, Rule,
+    ScreenDefinition, Sort, Source, Universe,
+};
+use crate::screens::ScreenSummary;
+use crate::search::SearchResult;
+use crate::statements::Statements;
+use crate::stock::Stock;
+use crate::valuation::{Valuation, ValuationBand};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Stock screener API"),
+    paths(
+        crate::get_screeners,
+        crate::get_screener_results,
+        crate::export_screener_results,
+        crate::run_screener,
+        crate::get_screener_runs,
+        crate::get_screener_runs_diff,
+        crate::get_screener_run,
+        crate::get_screener_scores,
+        crate::get_screener_explanations,
+        crate::get_screener_explanation,
+        crate::force_screener_rerun,
+        crate::get_jobs,
+        crate::get_job,
+        crate::get_job_events,
+        crate::cancel_job,
+        crate::start_index,
+        crate::get_backtests,
+        crate::start_backtest,
+        crate::get_backtest,
+        crate::get_search,
+        crate::get_compare,
+        crate::get_stocks_batch,
+        crate::get_stock,
+        crate::get_valuation,
+        crate::get_dividends,
+        crate::get_income,
+        crate::get_balance,
+        crate::get_cash_flow,
+        crate::get_ratios,
+        crate::get_key_metrics,
+        crate::get_profile,
+        crate::get_dcf,
+        crate::export_stock,
+        crate::get_metrics,
+        crate::get_health,
+        crate::get_ready,
+    ),
+    components(schemas(
+        ApiError,
+        ErrorResponse,
+        IncomeStatement,
+        BalanceSheetStatement,
+        CashFlowStatement,
+        Ratios,
+        RatiosTTM,
+        KeyMetrics,
+        KeyMetricsTTM,
+        Profile,
+        AdvancedLeveredDiscountedCashFlow,
+        FetchStats,
+        IncomeRows,
+        BalanceRows,
+        CashFlowRows,
+        RatioRows,
+        KeyMetricRows,
+        Stock,
+        Statements,
+        Metrics,
+        Other,
+        Valuation,
+        ValuationBand,
+        DividendHistory,
+        DividendYear,
+        ScreenSummary,
+        ScreenDefinition,
+        Universe,
+        Rule,
+        Comparison,
+        Expr,
+        Field,
+        Source,
+        Aggregation,
+        Sort,
+        Ranking,
+        Factor,
+        Evaluation,
+        Outcome,
+        CriterionResult,
+        RankedStock,
+        FactorScore,
+        ScreenRun,
+        RunSummary,
+        RunDiff,
+        Job,
+        JobKind,
+        JobStatus,
+        JobEvent,
+        JobProgress,
+        PassedStock,
+        Metric,
+        BacktestRequest,
+        Backtest,
+        BacktestPeriod,
+        BacktestSummary,
+        SearchResult,
+        StockComparison,
+        CompareField,
+        FieldSeries,
+        StockSeries,
+        BatchRequest,
+        BatchResult,
+    )),
+    tags(
+        (name = "stocks", description = "Statements, metrics and valuations of a single stock"),
+        (name = "screens", description = "Screen definitions, results and their history"),
+        (name = "jobs", description = "Background work and its progress"),
+        (name = "backtests", description = "Screens run against past data"),
+        (name = "operations", description = "Metrics, liveness and readiness of the server"),
+    )
+)]
+pub struct ApiDoc;
@@ -1,4 +1,4 @@
-use crate::helper_functions::api;
+use crate::helper_functions::api_result;
 use crate::helper_structs::{AdvancedLeveredDiscountedCashFlow, Profile, TimePeriod};
 use serde::de::DeserializeOwned;
 
@@ -6,8 +6,9 @@ use core::fmt::Debug;
 use std::any::TypeId;
 
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Other {
     pub profile: Vec<Profile>,
     pub dcf: Vec<AdvancedLeveredDiscountedCashFlow>,
@@ -21,14 +22,27 @@ impl Other {
         }
     }
 
-    pub async fn fetch<T>(&mut self, symbol: &String)
+    // Neither has fetch stats, an empty list is one that was not fetched or came back empty
+    pub fn merge(&mut self, fetched: Other) {
+        if !fetched.profile.is_empty() {
+            self.profile = fetched.profile;
+        }
+
+        if !fetched.dcf.is_empty() {
+            self.dcf = fetched.dcf;
+        }
+    }
+
+    pub async fn fetch<T>(&mut self, symbol: &String) -> Result<(), reqwest::Error>
     where
         T: DeserializeOwned + Debug + 'static,
     {
         if TypeId::of::<T>() == TypeId::of::<Profile>() {
-            self.profile = api::<Profile>(&TimePeriod::NA(), symbol, "".to_string()).await;
+            self.profile = api_result::<Profile>(&TimePeriod::NA(), symbol, "".to_string()).await?;
         } else if TypeId::of::<T>() == TypeId::of::<AdvancedLeveredDiscountedCashFlow>() {
-            self.dcf = api::<AdvancedLeveredDiscountedCashFlow>(&TimePeriod::NA(), symbol, "".to_string()).await;
+            self.dcf = api_result::<AdvancedLeveredDiscountedCashFlow>(&TimePeriod::NA(), symbol, "".to_string()).await?;
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use chrono::NaiveDate;
+
+use crate::errors::validate_symbol;
+use crate::helper_functions::api_object;
+use crate::helper_structs::{HistoricalPrice, HistoricalPriceFull, TimePeriod};
+
+const PRICES_DIR: &str = "prices";
+
+// Daily closes change every trading day, older files are fetched again
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Oldest first, read from the local store and only fetched when the stored copy is missing or old.
+// The symbol names the stored file, anything that is not a ticker is refused before touching it.
+pub async fn history(symbol: &str) -> Vec<HistoricalPrice> {
+    let symbol = match validate_symbol(symbol) {
+        Ok(symbol) => symbol,
+        Err(e) => {
+            tracing::warn!(symbol = %symbol, error = %e.message, "not loading prices");
+            return vec![];
+        }
+    };
+    let path = PathBuf::from(PRICES_DIR).join(format!("{}.json", symbol));
+
+    let age = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+    if age.is_some_and(|age| age < MAX_AGE) {
+        if let Some(prices) = read(&path) {
+            return prices;
+        }
+    }
+
+    let fetched = api_object::<HistoricalPriceFull>(&TimePeriod::NA(), &symbol, "".to_string())
+        .await
+        .map(|full| full.historical)
+        .unwrap_or_default();
+
+    // A failed fetch falls back to whatever was stored before
+    if fetched.is_empty() {
+        return read(&path).unwrap_or_default();
+    }
+
+    let mut prices = fetched;
+    prices.sort_by(|a, b| a.date.cmp(&b.date));
+
+    if let Err(e) = fs::create_dir_all(PRICES_DIR)
+        .and_then(|_| fs::write(&path, serde_json::to_string(&prices).unwrap()))
+    {
+        tracing::warn!(symbol = %symbol, error = %e, "could not store prices");
+    }
+
+    prices
+}
+
+// Last close on or before the date, prices have to be sorted oldest first
+pub fn close_on(prices: &[HistoricalPrice], date: NaiveDate) -> Option<f64> {
+    let date = date.format("%Y-%m-%d").to_string();
+    let i = prices.partition_point(|p| p.date <= date);
+
+    prices[..i]
+        .iter()
+        .rev()
+        .find_map(|p| p.adj_close.or(p.close))
+}
+
+fn read(path: &PathBuf) -> Option<Vec<HistoricalPrice>> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
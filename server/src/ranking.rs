@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::rules::{Evaluation, Expr};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Ranking {
+    pub factors: Vec<Factor>,
+    #[serde(default)]
+    pub top_n: Option<usize>,
+    // Composite scores run from 0 (worst in the universe) to 1 (best)
+    #[serde(default)]
+    pub min_score: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Factor {
+    pub name: String,
+    pub value: Expr,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default = "default_higher_is_better")]
+    pub higher_is_better: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RankedStock {
+    pub symbol: String,
+    pub rank: usize,
+    pub score: f64,
+    pub factors: Vec<FactorScore>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct FactorScore {
+    pub name: String,
+    pub value: f64,
+    // 1 is the best value for this factor within the universe
+    pub rank: usize,
+    pub percentile: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_higher_is_better() -> bool {
+    true
+}
+
+impl Ranking {
+    // Scores every stock that passed the criteria and had a value for each factor, returning
+    // them best first with the top_n and min_score cutoffs applied
+    pub fn rank(&self, evaluations: &[Evaluation]) -> Vec<RankedStock> {
+        let candidates: Vec<(&Evaluation, Vec<f64>)> = evaluations
+            .iter()
+            .filter(|e| e.passed && e.factors.len() == self.factors.len())
+            .filter_map(|e| {
+                let values: Option<Vec<f64>> = e.factors.iter().copied().collect();
+                values
+                    .filter(|values| values.iter().all(|v| v.is_finite()))
+                    .map(|values| (e, values))
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            return vec![];
+        }
+
+        let factor_ranks: Vec<Vec<(usize, f64)>> = self
+            .factors
+            .iter()
+            .enumerate()
+            .map(|(i, factor)| {
+                let values: Vec<f64> = candidates.iter().map(|(_, v)| v[i]).collect();
+                percentiles(&values, factor.higher_is_better)
+            })
+            .collect();
+
+        let total_weight: f64 = self.factors.iter().map(|f| f.weight).sum();
+
+        let mut ranked: Vec<RankedStock> = candidates
+            .iter()
+            .enumerate()
+            .map(|(c, (evaluation, values))| {
+                let factors: Vec<FactorScore> = self
+                    .factors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, factor)| FactorScore {
+                        name: factor.name.clone(),
+                        value: values[i],
+                        rank: factor_ranks[i][c].0,
+                        percentile: factor_ranks[i][c].1,
+                    })
+                    .collect();
+
+                let score = if total_weight > 0.0 {
+                    self.factors
+                        .iter()
+                        .zip(&factors)
+                        .map(|(factor, s)| factor.weight * s.percentile)
+                        .sum::<f64>()
+                        / total_weight
+                } else {
+                    0.0
+                };
+
+                RankedStock {
+                    symbol: evaluation.symbol.clone(),
+                    rank: 0,
+                    score,
+                    factors,
+                }
+            })
+            .filter(|r| self.min_score.is_none_or(|min| r.score >= min))
+            .collect();
+
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        if let Some(top_n) = self.top_n {
+            ranked.truncate(top_n);
+        }
+
+        for (i, r) in ranked.iter_mut().enumerate() {
+            r.rank = i + 1;
+        }
+
+        ranked
+    }
+}
+
+// Rank (1 is best) and percentile (1.0 is best) of each value, ties share the better rank
+fn percentiles(values: &[f64], higher_is_better: bool) -> Vec<(usize, f64)> {
+    let n = values.len();
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    values
+        .iter()
+        .map(|v| {
+            let better = if higher_is_better {
+                n - sorted.partition_point(|other| other <= v)
+            } else {
+                sorted.partition_point(|other| other < v)
+            };
+
+            let percentile = if n > 1 {
+                1.0 - better as f64 / (n - 1) as f64
+            } else {
+                1.0
+            };
+
+            (better + 1, percentile)
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::errors::ApiError;
+use crate::helper_structs::{
+    AdvancedLeveredDiscountedCashFlow, BalanceSheetStatement, CashFlowStatement, IncomeStatement,
+    KeyMetrics, KeyMetricsTTM, Profile, Ratios, RatiosTTM, TimePeriod,
+};
+use crate::stock::Stock;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PeriodQuery {
+    /// annual (the default), quarter or ttm
+    pub period: Option<String>,
+    /// How many periods, 10 when not given
+    pub limit: Option<u8>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+#[aliases(
+    IncomeRows = PeriodRows<IncomeStatement>,
+    BalanceRows = PeriodRows<BalanceSheetStatement>,
+    CashFlowRows = PeriodRows<CashFlowStatement>,
+    RatioRows = PeriodRows<Ratios>,
+    KeyMetricRows = PeriodRows<KeyMetrics>
+)]
+pub struct PeriodRows<T> {
+    pub symbol: String,
+    pub period: String,
+    // Newest first, at most the requested limit
+    pub rows: Vec<T>,
+}
+
+impl PeriodQuery {
+    // Ten annual periods when nothing is asked for
+    pub fn time_period(&self) -> Result<TimePeriod, String> {
+        let limit = self.limit.unwrap_or(10);
+
+        if limit == 0 {
+            return Err("limit has to be at least 1".to_string());
+        }
+
+        match self.period.as_deref().unwrap_or("annual") {
+            "annual" => Ok(TimePeriod::Annual(limit)),
+            "quarter" => Ok(TimePeriod::Quarter(limit)),
+            "ttm" => Ok(TimePeriod::TTM()),
+            other => Err(format!(
+                "unknown period {}, expected annual, quarter or ttm",
+                other
+            )),
+        }
+    }
+}
+
+impl<T: Clone> PeriodRows<T> {
+    fn new(stock: &Stock, period: &TimePeriod, rows: &[T]) -> Self {
+        let (name, limit) = match period {
+            TimePeriod::Annual(v) => ("annual", *v as usize),
+            TimePeriod::Quarter(v) => ("quarter", *v as usize),
+            TimePeriod::TTM() => ("ttm", 1),
+            TimePeriod::NA() => ("all", rows.len()),
+        };
+
+        Self {
+            symbol: stock.ticker.clone(),
+            period: name.to_string(),
+            rows: rows.iter().take(limit).cloned().collect(),
+        }
+    }
+}
+
+pub async fn income(
+    stock: &mut Stock,
+    period: TimePeriod,
+) -> Result<PeriodRows<IncomeStatement>, ApiError> {
+    stock.income(period.clone()).await?;
+
+    let statements = &stock.statements;
+    let rows = match period {
+        TimePeriod::Quarter(_) => &statements.quarter_income.0,
+        TimePeriod::TTM() => &statements.ttm_income.0,
+        _ => &statements.annual_income.0,
+    };
+
+    Ok(PeriodRows::new(stock, &period, rows))
+}
+
+pub async fn balance(
+    stock: &mut Stock,
+    period: TimePeriod,
+) -> Result<PeriodRows<BalanceSheetStatement>, ApiError> {
+    stock.balance(period.clone()).await?;
+
+    let rows = match period {
+        TimePeriod::Quarter(_) => &stock.statements.quarter_balance.0,
+        _ => &stock.statements.annual_balance.0,
+    };
+
+    Ok(PeriodRows::new(stock, &period, rows))
+}
+
+pub async fn cash_flow(
+    stock: &mut Stock,
+    period: TimePeriod,
+) -> Result<PeriodRows<CashFlowStatement>, ApiError> {
+    stock.cash(period.clone()).await?;
+
+    let rows = match period {
+        TimePeriod::Quarter(_) => &stock.statements.quarter_cash.0,
+        _ => &stock.statements.annual_cash.0,
+    };
+
+    Ok(PeriodRows::new(stock, &period, rows))
+}
+
+pub async fn ratios(stock: &mut Stock, period: TimePeriod) -> Result<PeriodRows<Ratios>, ApiError> {
+    stock.ratios(period.clone()).await?;
+
+    let rows = match period {
+        TimePeriod::Quarter(_) => &stock.metrics.quarter_ratios.0,
+        _ => &stock.metrics.annual_ratios.0,
+    };
+
+    Ok(PeriodRows::new(stock, &period, rows))
+}
+
+pub async fn ratios_ttm(stock: &mut Stock) -> Result<PeriodRows<RatiosTTM>, ApiError> {
+    stock.ratios_ttm().await?;
+
+    Ok(PeriodRows::new(
+        stock,
+        &TimePeriod::TTM(),
+        &stock.metrics.ttm_ratios.0,
+    ))
+}
+
+pub async fn key_metrics(
+    stock: &mut Stock,
+    period: TimePeriod,
+) -> Result<PeriodRows<KeyMetrics>, ApiError> {
+    stock.key_metrics(period.clone()).await?;
+
+    let rows = match period {
+        TimePeriod::Quarter(_) => &stock.metrics.quarter_key_metrics.0,
+        _ => &stock.metrics.annual_key_metrics.0,
+    };
+
+    Ok(PeriodRows::new(stock, &period, rows))
+}
+
+pub async fn key_metrics_ttm(stock: &mut Stock) -> Result<PeriodRows<KeyMetricsTTM>, ApiError> {
+    stock.key_metrics_ttm().await?;
+
+    Ok(PeriodRows::new(
+        stock,
+        &TimePeriod::TTM(),
+        &stock.metrics.ttm_key_metrics.0,
+    ))
+}
+
+pub async fn profile(stock: &mut Stock) -> Result<Option<Profile>, ApiError> {
+    if stock.other.profile.is_empty() {
+        stock.profile().await?;
+    }
+
+    Ok(stock.other.profile.first().cloned())
+}
+
+pub async fn dcf(stock: &mut Stock) -> Result<Option<AdvancedLeveredDiscountedCashFlow>, ApiError> {
+    if stock.other.dcf.is_empty() {
+        stock.dcf().await?;
+    }
+
+    Ok(stock.other.dcf.first().cloned())
+}
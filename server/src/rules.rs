@@ -0,0 +1,760 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::errors::ApiError;
+use crate::helper_structs::{AvailableTraded, Profile, TimePeriod};
+use crate::ranking::Ranking;
+use crate::stock::Stock;
+use crate::utils;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ScreenDefinition {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub universe: Universe,
+    // Every criterion has to pass, they are checked in order so cheap ones should go first
+    pub criteria: Vec<Rule>,
+    #[serde(default)]
+    pub sort: Option<Sort>,
+    // Turns the screen into a ranking of the stocks that pass the criteria
+    #[serde(default)]
+    pub ranking: Option<Ranking>,
+    // How long results are served before the screen runs again, forever when not set
+    #[serde(default)]
+    pub ttl_hours: Option<i64>,
+}
+
+impl ScreenDefinition {
+    pub fn is_expired(&self, finished_at: DateTime<Utc>) -> bool {
+        self.ttl_hours
+            .is_some_and(|ttl| Utc::now() - finished_at > Duration::hours(ttl))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Universe {
+    // NYSE and NASDAQ when not given, any exchange when set to an empty list
+    #[serde(default = "default_exchanges")]
+    pub exchanges: Vec<String>,
+    #[serde(default = "default_types")]
+    pub types: Vec<String>,
+    // A fixed list of tickers to screen instead of everything traded on the exchanges
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub exclude_symbols: Vec<String>,
+
+    // Everything below is checked against the company profile, so it costs a fetch per stock
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub sectors: Vec<String>,
+    #[serde(default)]
+    pub exclude_sectors: Vec<String>,
+    #[serde(default)]
+    pub industries: Vec<String>,
+    #[serde(default)]
+    pub exclude_industries: Vec<String>,
+    // Not set means no check, false leaves them out and true lets them in
+    #[serde(default)]
+    pub etfs: Option<bool>,
+    #[serde(default)]
+    pub funds: Option<bool>,
+    #[serde(default)]
+    pub adrs: Option<bool>,
+    #[serde(default)]
+    pub min_market_cap: Option<f64>,
+    #[serde(default)]
+    pub max_market_cap: Option<f64>,
+    // Liquidity, as average daily shares traded and the dollar value of that volume
+    #[serde(default)]
+    pub min_volume: Option<f64>,
+    #[serde(default)]
+    pub min_dollar_volume: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Sort {
+    pub by: Expr,
+    #[serde(default = "default_descending")]
+    pub descending: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    All(Vec<Rule>),
+    Any(Vec<Rule>),
+    Not(Box<Rule>),
+    Compare {
+        left: Expr,
+        op: Comparison,
+        right: Expr,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Eq,
+    Ne,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expr {
+    Const(f64),
+    Field(Field),
+    // Arithmetic folds left over its operands, so sub = [a, b, c] is a - b - c
+    Add(Vec<Expr>),
+    Sub(Vec<Expr>),
+    Mul(Vec<Expr>),
+    Div(Vec<Expr>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Field {
+    pub source: Source,
+    // {"Annual": 5}, {"Quarter": 8}, {"TTM": []} or {"NA": []}
+    #[schema(value_type = Object)]
+    pub period: TimePeriod,
+    // Name of the field as the api returns it, e.g. "eps" or "returnOnEquity"
+    pub name: String,
+    #[serde(default)]
+    pub aggregation: Aggregation,
+    // Skips the newest rows of the period, e.g. the oldest three of ten years is offset 7
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Income,
+    Balance,
+    CashFlow,
+    Ratios,
+    KeyMetrics,
+    Profile,
+    Dcf,
+    // Annual dividend history derived from the income and cash flow statements
+    Dividends,
+}
+
+// Statements come newest first, so index 0 is the most recent period
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    #[default]
+    Latest,
+    At(usize),
+    Mean,
+    Median,
+    Min,
+    Max,
+    Sum,
+    // Compound growth per period between the oldest and newest value
+    Cagr,
+    // Number of periods available, the field name is ignored
+    Count,
+    // Consecutive periods, newest first, in which the value grew
+    Streak,
+}
+
+impl Default for Universe {
+    fn default() -> Self {
+        Self {
+            exchanges: default_exchanges(),
+            types: default_types(),
+            symbols: vec![],
+            exclude_symbols: vec![],
+            countries: vec![],
+            sectors: vec![],
+            exclude_sectors: vec![],
+            industries: vec![],
+            exclude_industries: vec![],
+            etfs: None,
+            funds: None,
+            adrs: None,
+            min_market_cap: None,
+            max_market_cap: None,
+            min_volume: None,
+            min_dollar_volume: None,
+        }
+    }
+}
+
+impl Universe {
+    pub fn contains(&self, symbol: &AvailableTraded) -> bool {
+        let type_allowed = self.types.contains(&symbol.type_)
+            || (symbol.type_ == "etf" && self.etfs == Some(true))
+            || (symbol.type_ == "fund" && self.funds == Some(true));
+
+        type_allowed
+            && (self.exchanges.is_empty() || self.exchanges.contains(&symbol.exchange_short_name))
+            && !self.excludes_symbol(&symbol.symbol)
+    }
+
+    pub fn excludes_symbol(&self, symbol: &str) -> bool {
+        self.exclude_symbols
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(symbol))
+    }
+
+    pub fn needs_profile(&self) -> bool {
+        !self.countries.is_empty()
+            || !self.sectors.is_empty()
+            || !self.exclude_sectors.is_empty()
+            || !self.industries.is_empty()
+            || !self.exclude_industries.is_empty()
+            || self.etfs.is_some()
+            || self.funds.is_some()
+            || self.adrs.is_some()
+            || self.min_market_cap.is_some()
+            || self.max_market_cap.is_some()
+            || self.min_volume.is_some()
+            || self.min_dollar_volume.is_some()
+    }
+
+    // A missing profile only gets through when nothing has to be looked up in it
+    pub fn contains_profile(&self, profile: Option<&Profile>) -> bool {
+        let profile = match profile {
+            Some(profile) => profile,
+            None => return !self.needs_profile(),
+        };
+
+        let dollar_volume = match (profile.vol_avg, profile.price) {
+            (Some(volume), Some(price)) => Some(volume * price),
+            _ => None,
+        };
+
+        allowed_by(&self.countries, profile.country.as_ref())
+            && allowed_by(&self.sectors, profile.sector.as_ref())
+            && !listed(&self.exclude_sectors, profile.sector.as_ref())
+            && allowed_by(&self.industries, profile.industry.as_ref())
+            && !listed(&self.exclude_industries, profile.industry.as_ref())
+            && flag_allowed(self.etfs, profile.is_etf)
+            && flag_allowed(self.funds, profile.is_fund)
+            && flag_allowed(self.adrs, profile.is_adr)
+            && within(self.min_market_cap, self.max_market_cap, profile.mkt_cap)
+            && within(self.min_volume, None, profile.vol_avg)
+            && within(self.min_dollar_volume, None, dollar_volume)
+    }
+}
+
+fn allowed_by(list: &[String], value: Option<&String>) -> bool {
+    list.is_empty() || listed(list, value)
+}
+
+fn listed(list: &[String], value: Option<&String>) -> bool {
+    value.is_some_and(|value| list.iter().any(|l| l.eq_ignore_ascii_case(value)))
+}
+
+fn flag_allowed(allowed: Option<bool>, flag: Option<bool>) -> bool {
+    allowed != Some(false) || flag != Some(true)
+}
+
+fn within(min: Option<f64>, max: Option<f64>, value: Option<f64>) -> bool {
+    if min.is_none() && max.is_none() {
+        return true;
+    }
+
+    value.is_some_and(|v| min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max))
+}
+
+fn default_exchanges() -> Vec<String> {
+    vec![String::from("NYSE"), String::from("NASDAQ")]
+}
+
+fn default_types() -> Vec<String> {
+    vec![String::from("stock")]
+}
+
+impl Sort {
+    // Stocks without a value for the sort key always go last
+    pub fn order(&self, a: Option<f64>, b: Option<f64>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) if self.descending => b.total_cmp(&a),
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+fn default_descending() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Evaluation {
+    pub symbol: String,
+    pub passed: bool,
+    // Outside the screen's universe once the company profile was checked
+    #[serde(default)]
+    pub excluded: bool,
+    // Index into the screen's criteria of the first one that failed
+    pub failed_at: Option<usize>,
+    pub criteria: Vec<CriterionResult>,
+    // Values of the ranking factors, only filled in for stocks that passed
+    #[serde(default)]
+    pub factors: Vec<Option<f64>>,
+    // The provider error that kept the stock's data from being fetched, nothing was evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    // Not evaluated because an earlier criterion already failed
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CriterionResult {
+    pub description: String,
+    pub outcome: Outcome,
+    pub left: Option<f64>,
+    pub op: Option<Comparison>,
+    pub right: Option<f64>,
+    pub children: Vec<CriterionResult>,
+}
+
+impl ScreenDefinition {
+    // A stock whose data could not be fetched is not evaluated, the evaluation carries the error
+    pub async fn evaluate(&self, stock: &mut Stock) -> Evaluation {
+        match self.fetch(stock).await {
+            Ok(()) => self.evaluate_offline(stock),
+            Err(e) => {
+                tracing::warn!(
+                    screen = %self.name,
+                    symbol = %stock.ticker,
+                    error = %e.message,
+                    "could not fetch the stock"
+                );
+                Evaluation {
+                    symbol: stock.ticker.clone(),
+                    passed: false,
+                    excluded: false,
+                    failed_at: None,
+                    criteria: self.criteria.iter().map(CriterionResult::skipped).collect(),
+                    factors: vec![],
+                    error: Some(e.message),
+                }
+            }
+        }
+    }
+
+    // Pulls every section the screen reads before anything is evaluated, the profile first so a
+    // stock outside the universe costs no further calls. Stops at the first section that fails.
+    pub async fn fetch(&self, stock: &mut Stock) -> Result<(), ApiError> {
+        if self.universe.needs_profile() {
+            stock
+                .fetch_source(Source::Profile, TimePeriod::NA())
+                .await?;
+
+            if !self.universe.contains_profile(stock.other.profile.first()) {
+                return Ok(());
+            }
+        }
+
+        for (source, period) in self.sections() {
+            stock.fetch_source(source, period.clone()).await?;
+        }
+
+        Ok(())
+    }
+
+    // Every field the criteria, ranking and sort read
+    pub fn fields(&self) -> Vec<&Field> {
+        let mut fields: Vec<&Field> = self.criteria.iter().flat_map(|r| r.fields()).collect();
+
+        if let Some(ranking) = &self.ranking {
+            fields.extend(ranking.factors.iter().flat_map(|f| f.value.fields()));
+        }
+
+        if let Some(sort) = &self.sort {
+            fields.extend(sort.by.fields());
+        }
+
+        fields
+    }
+
+    // Each section and period the fields read, once
+    fn sections(&self) -> Vec<(Source, &TimePeriod)> {
+        let mut sections = vec![];
+
+        for field in self.fields() {
+            if !sections.contains(&(field.source, &field.period)) {
+                sections.push((field.source, &field.period));
+            }
+        }
+
+        sections
+    }
+
+    // Fingerprint of the rows the screen reads from the stock. Pulling a section the screen
+    // doesn't read, or pulling the same rows again, leaves it unchanged.
+    pub fn data_stamp(&self, stock: &Stock) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (source, period) in self.sections() {
+            let rows = stock.rows(source, period);
+            serde_json::to_string(&rows)
+                .unwrap_or_default()
+                .hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    // Only looks at the data the stock already has, also used on copies cut back to a past date
+    #[tracing::instrument(
+        name = "evaluate",
+        skip_all,
+        fields(
+            screen = %self.name,
+            symbol = %stock.ticker,
+            passed = tracing::field::Empty,
+        )
+    )]
+    pub fn evaluate_offline(&self, stock: &Stock) -> Evaluation {
+        let mut criteria = vec![];
+        let mut failed_at = None;
+
+        if self.universe.needs_profile()
+            && !self.universe.contains_profile(stock.other.profile.first())
+        {
+            tracing::Span::current().record("passed", false);
+            return Evaluation {
+                symbol: stock.ticker.clone(),
+                passed: false,
+                excluded: true,
+                failed_at: None,
+                criteria: self.criteria.iter().map(CriterionResult::skipped).collect(),
+                factors: vec![],
+                error: None,
+            };
+        }
+
+        for (i, rule) in self.criteria.iter().enumerate() {
+            if failed_at.is_some() {
+                criteria.push(CriterionResult::skipped(rule));
+                continue;
+            }
+
+            let result = rule.explain(stock);
+
+            if result.outcome == Outcome::Failed {
+                failed_at = Some(i);
+            }
+
+            criteria.push(result);
+        }
+
+        let mut factors = vec![];
+
+        if let (None, Some(ranking)) = (failed_at, &self.ranking) {
+            for factor in &ranking.factors {
+                factors.push(factor.value.evaluate(stock));
+            }
+        }
+
+        tracing::Span::current().record("passed", failed_at.is_none());
+
+        Evaluation {
+            symbol: stock.ticker.clone(),
+            passed: failed_at.is_none(),
+            excluded: false,
+            failed_at,
+            criteria,
+            factors,
+            error: None,
+        }
+    }
+}
+
+impl CriterionResult {
+    fn skipped(rule: &Rule) -> Self {
+        Self {
+            description: rule.to_string(),
+            outcome: Outcome::Skipped,
+            left: None,
+            op: None,
+            right: None,
+            children: vec![],
+        }
+    }
+}
+
+impl Rule {
+    pub fn fields(&self) -> Vec<&Field> {
+        match self {
+            Rule::All(rules) | Rule::Any(rules) => rules.iter().flat_map(|r| r.fields()).collect(),
+            Rule::Not(rule) => rule.fields(),
+            Rule::Compare { left, right, .. } => {
+                let mut fields = left.fields();
+                fields.extend(right.fields());
+                fields
+            }
+        }
+    }
+
+    pub fn explain(&self, stock: &Stock) -> CriterionResult {
+        let combine = |children: Vec<CriterionResult>, passed: bool| CriterionResult {
+            description: self.to_string(),
+            outcome: if passed {
+                Outcome::Passed
+            } else {
+                Outcome::Failed
+            },
+            left: None,
+            op: None,
+            right: None,
+            children,
+        };
+
+        match self {
+            Rule::All(rules) => {
+                let children: Vec<CriterionResult> =
+                    rules.iter().map(|r| r.explain(stock)).collect();
+                let passed = children.iter().all(|c| c.outcome == Outcome::Passed);
+                combine(children, passed)
+            }
+            Rule::Any(rules) => {
+                let children: Vec<CriterionResult> =
+                    rules.iter().map(|r| r.explain(stock)).collect();
+                let passed = children.iter().any(|c| c.outcome == Outcome::Passed);
+                combine(children, passed)
+            }
+            Rule::Not(rule) => {
+                let child = rule.explain(stock);
+                let passed = child.outcome != Outcome::Passed;
+                combine(vec![child], passed)
+            }
+            Rule::Compare { left, op, right } => {
+                let left = left.evaluate(stock);
+                let right = right.evaluate(stock);
+                let passed = match (left, right) {
+                    (Some(left), Some(right)) => op.compare(left, right),
+                    _ => false,
+                };
+
+                CriterionResult {
+                    description: self.to_string(),
+                    outcome: if passed {
+                        Outcome::Passed
+                    } else {
+                        Outcome::Failed
+                    },
+                    left,
+                    op: Some(*op),
+                    right,
+                    children: vec![],
+                }
+            }
+        }
+    }
+}
+
+impl Comparison {
+    pub fn compare(&self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Gt => left > right,
+            Comparison::Gte => left >= right,
+            Comparison::Lt => left < right,
+            Comparison::Lte => left <= right,
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+        }
+    }
+}
+
+impl Expr {
+    pub fn fields(&self) -> Vec<&Field> {
+        match self {
+            Expr::Const(_) => vec![],
+            Expr::Field(field) => vec![field],
+            Expr::Add(operands)
+            | Expr::Sub(operands)
+            | Expr::Mul(operands)
+            | Expr::Div(operands) => operands.iter().flat_map(|e| e.fields()).collect(),
+        }
+    }
+
+    // Arithmetic can overflow or give NaN (inf - inf), which is treated like a missing value
+    pub fn evaluate(&self, stock: &Stock) -> Option<f64> {
+        let value = match self {
+            Expr::Const(v) => Some(*v),
+            Expr::Field(field) => field.evaluate(stock),
+            Expr::Add(operands) => fold(operands, stock, |a, b| Some(a + b)),
+            Expr::Sub(operands) => fold(operands, stock, |a, b| Some(a - b)),
+            Expr::Mul(operands) => fold(operands, stock, |a, b| Some(a * b)),
+            Expr::Div(operands) => fold(operands, stock, |a, b| (b != 0.0).then_some(a / b)),
+        }?;
+
+        value.is_finite().then_some(value)
+    }
+}
+
+fn fold(operands: &[Expr], stock: &Stock, op: fn(f64, f64) -> Option<f64>) -> Option<f64> {
+    let (first, rest) = operands.split_first()?;
+    let mut value = first.evaluate(stock)?;
+
+    for operand in rest {
+        value = op(value, operand.evaluate(stock)?)?;
+    }
+
+    Some(value)
+}
+
+impl Field {
+    pub fn evaluate(&self, stock: &Stock) -> Option<f64> {
+        let rows: Vec<Value> = stock
+            .rows(self.source, &self.period)
+            .into_iter()
+            .skip(self.offset)
+            .collect();
+        let value = |row: &Value| row[&self.name].as_f64();
+
+        match &self.aggregation {
+            Aggregation::Latest => rows.first().and_then(value),
+            Aggregation::At(i) => rows.get(*i).and_then(value),
+            Aggregation::Count => Some(rows.len() as f64),
+            Aggregation::Streak => {
+                let values: Vec<Option<f64>> = rows.iter().map(value).collect();
+                Some(utils::growth_streak(&values) as f64)
+            }
+            Aggregation::Cagr => {
+                let newest = rows.first().and_then(value)?;
+                let oldest = rows.last().and_then(value)?;
+
+                if rows.len() < 2 || newest <= 0.0 || oldest <= 0.0 {
+                    return None;
+                }
+
+                Some((newest / oldest).powf(1.0 / (rows.len() - 1) as f64) - 1.0)
+            }
+            aggregation => {
+                let mut values: Vec<f64> = rows.iter().filter_map(value).collect();
+
+                match aggregation {
+                    Aggregation::Mean => utils::mean(&values),
+                    Aggregation::Median => {
+                        values.sort_by(f64::total_cmp);
+                        utils::median(&values)
+                    }
+                    Aggregation::Min => values.into_iter().reduce(f64::min),
+                    Aggregation::Max => values.into_iter().reduce(f64::max),
+                    Aggregation::Sum if !values.is_empty() => Some(values.iter().sum()),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |rules: &Vec<Rule>| {
+            rules
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        match self {
+            Rule::All(rules) => write!(f, "all of ({})", join(rules)),
+            Rule::Any(rules) => write!(f, "any of ({})", join(rules)),
+            Rule::Not(rule) => write!(f, "not ({})", rule),
+            Rule::Compare { left, op, right } => write!(f, "{} {} {}", left, op, right),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Gt => ">",
+            Comparison::Gte => ">=",
+            Comparison::Lt => "<",
+            Comparison::Lte => "<=",
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+        };
+
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |operands: &Vec<Expr>, symbol: &str| {
+            operands
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join(symbol)
+        };
+
+        match self {
+            Expr::Const(v) => write!(f, "{}", v),
+            Expr::Field(field) => write!(f, "{}", field),
+            Expr::Add(operands) => write!(f, "({})", join(operands, " + ")),
+            Expr::Sub(operands) => write!(f, "({})", join(operands, " - ")),
+            Expr::Mul(operands) => write!(f, "({})", join(operands, " * ")),
+            Expr::Div(operands) => write!(f, "({})", join(operands, " / ")),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let source = serde_json::to_value(self.source).unwrap_or_default();
+        let period = match self.period {
+            TimePeriod::Annual(v) => format!("annual {}", v),
+            TimePeriod::Quarter(v) => format!("quarter {}", v),
+            TimePeriod::TTM() => String::from("ttm"),
+            TimePeriod::NA() => String::from("all"),
+        };
+        let period = match self.offset {
+            0 => period,
+            offset => format!("{} from {}", period, offset),
+        };
+        let field = format!(
+            "{}.{}[{}]",
+            source.as_str().unwrap_or_default(),
+            self.name,
+            period
+        );
+
+        match &self.aggregation {
+            Aggregation::Latest => write!(f, "{}", field),
+            Aggregation::At(i) => write!(f, "{}[{}]", field, i),
+            Aggregation::Count => write!(f, "count({})", field),
+            Aggregation::Mean => write!(f, "mean({})", field),
+            Aggregation::Median => write!(f, "median({})", field),
+            Aggregation::Min => write!(f, "min({})", field),
+            Aggregation::Max => write!(f, "max({})", field),
+            Aggregation::Sum => write!(f, "sum({})", field),
+            Aggregation::Cagr => write!(f, "cagr({})", field),
+            Aggregation::Streak => write!(f, "streak({})", field),
+        }
+    }
+}
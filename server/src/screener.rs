@@ -1,34 +1,77 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::vec;
 
+use tokio::task::JoinSet;
+
 use crate::helper_functions::api;
 use crate::helper_structs::{AvailableTraded, TimePeriod};
-use crate::{cache, CACHE};
+use crate::jobs::JobHandle;
+use crate::ranking::RankedStock;
+use crate::rules::{Evaluation, ScreenDefinition, Source, Universe};
+use crate::stock::Stock;
+use crate::{cache, CACHE, CONCURRENCY};
+
+#[derive(Debug)]
+pub struct ScreenResults {
+    // Cache indexes of the stocks that passed, in the screen's sort order
+    pub passed: Vec<usize>,
+    pub evaluations: Vec<Evaluation>,
+    // Scores of the stocks that made the cut, only for ranking screens
+    pub ranked: Vec<RankedStock>,
+}
 
 #[derive(Debug)]
 pub struct Screener {
     pub stocks_to_screen: Vec<usize>,
+    // How many stocks are fetched and evaluated at the same time
+    pub concurrency: usize,
 }
 
 impl Screener {
     pub fn new() -> Self {
         Self {
             stocks_to_screen: Vec::new(),
+            concurrency: CONCURRENCY.load(Ordering::Relaxed).max(1),
         }
     }
 
-    pub async fn init_screen(&mut self) {
+    pub async fn init_screen(&mut self, universe: &Universe) {
+        if !universe.symbols.is_empty() {
+            self.stocks_to_screen = Screener::ticker_list_to_stocks(universe).await;
+            return;
+        }
+
         let symbols =
             api::<AvailableTraded>(&TimePeriod::NA(), &"".to_string(), "".to_string()).await;
 
-        self.stocks_to_screen = Screener::symbols_to_stocks(symbols).await;
+        self.stocks_to_screen = Screener::symbols_to_stocks(symbols, universe).await;
+    }
+
+    async fn ticker_list_to_stocks(universe: &Universe) -> Vec<usize> {
+        let mut stocks = vec![];
+        for symbol in &universe.symbols {
+            if universe.excludes_symbol(symbol) {
+                continue;
+            }
+
+            let index = cache::get_or_add_stock(symbol.to_uppercase())
+                .await
+                .cache_index
+                .unwrap();
+
+            if !stocks.contains(&index) {
+                stocks.push(index);
+            }
+        }
+
+        stocks
     }
 
-    async fn symbols_to_stocks(symbols: Vec<AvailableTraded>) -> Vec<usize> {
+    async fn symbols_to_stocks(symbols: Vec<AvailableTraded>, universe: &Universe) -> Vec<usize> {
         let mut stocks = vec![];
         for stock in symbols {
-            if stock.type_ == "stock"
-                && (stock.exchange_short_name == "NYSE" || stock.exchange_short_name == "NASDAQ")
-            {
+            if universe.contains(&stock) {
                 stocks.push(
                     cache::get_or_add_stock(stock.symbol)
                         .await
@@ -41,138 +84,187 @@ impl Screener {
         stocks
     }
 
-    pub async fn buffetology_screener(&mut self) -> Vec<usize> {
-        let mut passed = vec![];
-        // self.stocks_to_screen.len()
-        for i in 0..self.stocks_to_screen.len() {
-            let stock = self.stocks_to_screen[i];
-            if self.is_buffetology_stock(stock).await {
-                passed.push(stock.to_owned());
+    // Stops early, with partial results, once the job is cancelled
+    pub async fn run(&mut self, screen: &ScreenDefinition, job: &JobHandle) -> ScreenResults {
+        let shared = Arc::new(screen.clone());
+        let mut pending = self.stocks_to_screen.clone().into_iter().enumerate();
+        let mut tasks = JoinSet::new();
+        let mut finished = vec![];
+
+        loop {
+            while tasks.len() < self.concurrency && !job.is_cancelled() {
+                match pending.next() {
+                    Some((position, stock)) => {
+                        let screen = shared.clone();
+
+                        tasks.spawn(async move {
+                            let evaluation = evaluate(&screen, stock).await;
+                            (position, stock, evaluation)
+                        });
+                    }
+                    None => break,
+                }
             }
-        }
-
-        passed
-    }
 
-    pub async fn index_everything(&mut self) {
-        // self.stocks_to_screen.len()
-        for i in 0..self.stocks_to_screen.len() {
-            let mut cache = CACHE.lock().await;
-            let stock = cache.get_mut(self.stocks_to_screen[i]).unwrap();
-            let current_percent =
-                ((i as f64 + 1.0) / (self.stocks_to_screen.len() as f64)) * 100.0;
+            let (position, stock, evaluation) = match tasks.join_next().await {
+                Some(Ok(result)) => result,
+                // Counted as processed, or the job would never reach its total
+                Some(Err(e)) => {
+                    tracing::error!(screen = %screen.name, error = %e, "evaluating a stock failed");
+                    job.advance().await;
+                    continue;
+                }
+                None => break,
+            };
 
-            stock.get_all().await;
+            if evaluation.passed {
+                job.pass(screen, &evaluation).await;
+            }
 
-            println!("{}%", current_percent);
+            job.advance().await;
+            finished.push((position, stock, evaluation));
         }
-    }
 
-    async fn is_buffetology_stock(&mut self, stock_index: usize) -> bool {
-        // stock
-        //     .get_needed_data(NeededData {
-        //         income: (true, TimePeriod::Annual(10)),
-        //         balance: (true, TimePeriod::Quarter(1)),
-        //         ratios: (true, TimePeriod::Annual(10)),
-        //         key_metrics: (true, TimePeriod::Annual(10)),
-        //         key_metrics_ttm: (true, TimePeriod::TTM()),
-        //         income_qtr: (false, TimePeriod::NA()),
-        //     })
-        //     .await;
-
-        let mut cache = CACHE.lock().await;
-        let stock = cache.get_mut(stock_index).unwrap();
-
-        stock.income(TimePeriod::Annual(10)).await;
-        let income_statements = stock.statements.annual_income.clone();
-
-        if income_statements.0.len() != 10 {
-            return false;
-        }
+        // Back in universe order, whatever order the evaluations completed in
+        finished.sort_by_key(|(position, _, _)| *position);
 
-        if income_statements.0[0].eps < income_statements.0[4].eps
-            || income_statements.0[4].eps < income_statements.0[9].eps
-        {
-            return false;
-        }
+        let mut passed = vec![];
+        let mut indexes = vec![];
+        let mut evaluations = vec![];
 
-        for i in 0..10 {
-            if income_statements.0[i].eps < Some(0.0) {
-                return false;
+        for (_, stock, evaluation) in finished {
+            if evaluation.passed {
+                passed.push(stock);
             }
-        }
 
-        stock.key_metrics(TimePeriod::Annual(10)).await;
-        let key_metrics = &stock.metrics.annual_key_metrics;
-
-        if key_metrics.0.len() != 10 {
-            return false;
+            indexes.push(stock);
+            evaluations.push(evaluation);
         }
 
-        let mut roic_mean = 0.0;
-
-        for i in 0..10 {
-            if let Some(roic) = key_metrics.0[i].roic {
-                roic_mean += roic;
-            }
+        let mut ranked = vec![];
+
+        if let Some(ranking) = &screen.ranking {
+            ranked = ranking.rank(&evaluations);
+            passed = ranked
+                .iter()
+                .filter_map(|r| {
+                    evaluations
+                        .iter()
+                        .position(|e| e.symbol == r.symbol)
+                        .map(|i| indexes[i])
+                })
+                .collect();
+        } else if let Some(sort) = &screen.sort {
+            let cache = CACHE.lock().await;
+            let mut keyed: Vec<(usize, Option<f64>)> = passed
+                .into_iter()
+                .map(|i| (i, sort.by.evaluate(cache.get(i).unwrap())))
+                .collect();
+
+            keyed.sort_by(|(_, a), (_, b)| sort.order(*a, *b));
+
+            passed = keyed.into_iter().map(|(i, _)| i).collect();
         }
 
-        roic_mean /= 10.0;
-
-        if roic_mean < 0.12 {
-            return false;
-        }
-
-        stock.ratios(TimePeriod::Annual(10)).await;
-        let ratios = &stock.metrics.annual_ratios;
-
-        if ratios.0.len() != 10 {
-            return false;
+        ScreenResults {
+            passed,
+            evaluations,
+            ranked,
         }
+    }
 
-        let mut roe_mean = 0.0;
-        for i in 0..10 {
-            if let Some(roe) = ratios.0[i].return_on_equity {
-                roe_mean += roe;
+    pub async fn index_everything(&mut self, job: &JobHandle) {
+        let mut pending = self.stocks_to_screen.clone().into_iter();
+        let mut tasks = JoinSet::new();
+
+        loop {
+            while tasks.len() < self.concurrency && !job.is_cancelled() {
+                match pending.next() {
+                    Some(index) => {
+                        tasks.spawn(async move {
+                            let mut stock = cache::clone_stock(index).await;
+                            if let Err(e) = stock.get_all().await {
+                                tracing::warn!(
+                                    symbol = %stock.ticker,
+                                    error = %e.message,
+                                    "could not index the stock"
+                                );
+                            }
+                            cache::store_stock(stock).await;
+                        });
+                    }
+                    None => break,
+                }
             }
-        }
-
-        roe_mean /= 10.0;
-
-        if roe_mean < 0.15 {
-            return false;
-        }
-
-        stock.key_metrics_ttm().await;
-        let key_metrics = &stock.metrics.ttm_key_metrics;
 
-        if key_metrics.0.len() == 0 {
-            return false;
+            match tasks.join_next().await {
+                Some(Ok(())) => job.advance().await,
+                Some(Err(e)) => {
+                    tracing::error!(error = %e, "indexing a stock failed");
+                    job.advance().await;
+                }
+                None => break,
+            }
         }
+    }
+}
 
-        let earnings_yield = stock.metrics.ttm_key_metrics.0[0].earnings_yield_TTM;
-
-        if earnings_yield < Some(0.03) {
-            return false;
+// Fetches every field the screen reads with extra periods going back before the backtest's start,
+// returning copies of the stocks for evaluating at past dates
+pub async fn load_history(
+    stocks: &[usize],
+    fields: Vec<(Source, TimePeriod)>,
+    concurrency: usize,
+    job: &JobHandle,
+) -> Vec<Stock> {
+    let fields = Arc::new(fields);
+    let mut pending = stocks.iter().copied().enumerate();
+    let mut tasks = JoinSet::new();
+    let mut loaded = vec![];
+
+    loop {
+        while tasks.len() < concurrency && !job.is_cancelled() {
+            match pending.next() {
+                Some((position, index)) => {
+                    let fields = fields.clone();
+
+                    tasks.spawn(async move {
+                        let mut stock = cache::clone_stock(index).await;
+
+                        for (source, period) in fields.iter() {
+                            let _ = stock.fetch_source(*source, period.clone()).await;
+                        }
+
+                        cache::store_stock(stock.clone()).await;
+                        (position, stock)
+                    });
+                }
+                None => break,
+            }
         }
 
-        stock.balance(TimePeriod::Quarter(1)).await;
-        let balance_sheet = &stock.statements.quarter_balance;
-
-        if let Some(long_term_debt) = balance_sheet.0[0].long_term_debt {
-            if let Some(net_income) = income_statements.0[0].net_income {
-                if long_term_debt > net_income * 5.0 {
-                    return false;
-                }
-            } else {
-                return false;
+        match tasks.join_next().await {
+            Some(Ok(result)) => {
+                job.advance().await;
+                loaded.push(result);
             }
-        } else {
-            return false;
+            Some(Err(e)) => {
+                tracing::error!(error = %e, "loading a stock's history failed");
+                job.advance().await;
+            }
+            None => break,
         }
+    }
 
-        // println!("{} YOU MADE IT!!!", stock.ticker);
+    loaded.sort_by_key(|(position, _)| *position);
+    loaded.into_iter().map(|(_, stock)| stock).collect()
+}
 
-        true
-    }
+// Fetches into a copy of the stock so the cache stays unlocked while waiting on the API
+async fn evaluate(screen: &ScreenDefinition, stock_index: usize) -> Evaluation {
+    let mut stock = cache::clone_stock(stock_index).await;
+    let evaluation = screen.evaluate(&mut stock).await;
+    cache::store_stock(stock).await;
+
+    evaluation
 }
@@ -0,0 +1,219 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use axum::Json;
+use serde::Serialize;
+use tokio::time;
+use utoipa::ToSchema;
+
+use crate::helper_structs::ResponseCache;
+use crate::ranking::Ranking;
+use crate::rules::{ScreenDefinition, Sort, Universe};
+use crate::{cache, history, CACHE, SCREENER_CACHE, SCREENS};
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ScreenSummary {
+    pub name: String,
+    pub description: String,
+    pub universe: Universe,
+    pub criteria: usize,
+    pub sort: Option<Sort>,
+    pub ranking: Option<Ranking>,
+    pub ttl_hours: Option<i64>,
+}
+
+impl From<&ScreenDefinition> for ScreenSummary {
+    fn from(screen: &ScreenDefinition) -> Self {
+        Self {
+            name: screen.name.clone(),
+            description: screen.description.clone(),
+            universe: screen.universe.clone(),
+            criteria: screen.criteria.len(),
+            sort: screen.sort.clone(),
+            ranking: screen.ranking.clone(),
+            ttl_hours: screen.ttl_hours,
+        }
+    }
+}
+
+pub async fn find_screen(name: &str) -> Option<ScreenDefinition> {
+    SCREENS
+        .lock()
+        .await
+        .iter()
+        .find(|screen| screen.name == name)
+        .cloned()
+}
+
+pub async fn summaries() -> Vec<ScreenSummary> {
+    SCREENS
+        .lock()
+        .await
+        .iter()
+        .map(ScreenSummary::from)
+        .collect()
+}
+
+// Drops cached results that have expired or whose stocks have had their statements updated
+pub async fn invalidate_stale() {
+    let screens = SCREENS.lock().await.clone();
+    let stocks = CACHE.lock().await;
+
+    SCREENER_CACHE.lock().await.retain(|res| {
+        let fresh = screens
+            .iter()
+            .find(|screen| screen.name == res.endpoint)
+            .is_some_and(|screen| !res.is_stale(screen, &stocks));
+
+        if !fresh {
+            tracing::info!(screen = %res.endpoint, "screen results are stale");
+        }
+
+        fresh
+    });
+}
+
+pub async fn invalidate(name: &str) {
+    SCREENER_CACHE.lock().await.retain(|res| res.endpoint != name);
+}
+
+// Serves the last run of each screen after a restart, without the per-stock explanations
+pub async fn restore_results() {
+    let screens = SCREENS.lock().await.clone();
+    let mut restored = vec![];
+
+    for screen in screens {
+        let run = match history::latest_run(&screen).await {
+            Some(run) => run,
+            None => continue,
+        };
+
+        let mut constituents = vec![];
+
+        for symbol in &run.symbols {
+            if let Some(i) = cache::stock_index_in_cache(symbol.clone()).await {
+                constituents.push((i, screen.data_stamp(&CACHE.lock().await[i])));
+            }
+        }
+
+        restored.push(ResponseCache {
+            endpoint: screen.name,
+            data: Json(run.symbols),
+            evaluations: vec![],
+            ranked: run.ranked,
+            finished_at: run.finished_at,
+            constituents,
+        });
+    }
+
+    tracing::info!(screens = restored.len(), "restored screen results");
+    SCREENER_CACHE.lock().await.extend(restored);
+}
+
+pub fn load(dir: &Path) -> Vec<ScreenDefinition> {
+    let mut screens = vec![];
+
+    if !dir.is_dir() {
+        tracing::warn!(dir = %dir.display(), "screens directory does not exist");
+    }
+
+    for path in screen_files(dir) {
+        let contents = match fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "could not read screen");
+                continue;
+            }
+        };
+
+        let screen = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                toml::from_str::<ScreenDefinition>(&contents).map_err(|e| e.to_string())
+            }
+            _ => serde_json::from_str::<ScreenDefinition>(&contents).map_err(|e| e.to_string()),
+        };
+
+        match screen {
+            Ok(screen) => {
+                if screens
+                    .iter()
+                    .any(|s: &ScreenDefinition| s.name == screen.name)
+                {
+                    tracing::warn!(
+                        path = %path.display(),
+                        screen = %screen.name,
+                        "skipping screen, one with the same name is already loaded"
+                    );
+                } else {
+                    screens.push(screen);
+                }
+            }
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "could not parse screen"),
+        }
+    }
+
+    screens.sort_by(|a, b| a.name.cmp(&b.name));
+    screens
+}
+
+// Polls the directory and swaps in the new definitions whenever a file is added, removed or edited
+pub async fn watch(dir: PathBuf) {
+    let mut interval = time::interval(Duration::from_secs(5));
+    let mut last_seen = modified_times(&dir);
+
+    loop {
+        interval.tick().await;
+
+        let seen = modified_times(&dir);
+
+        if seen == last_seen {
+            continue;
+        }
+
+        last_seen = seen;
+
+        let screens = load(&dir);
+        let previous = std::mem::replace(&mut *SCREENS.lock().await, screens.clone());
+
+        tracing::info!(screens = screens.len(), dir = %dir.display(), "reloaded screens");
+
+        // Results of a screen whose definition changed are no longer valid
+        SCREENER_CACHE.lock().await.retain(|res| {
+            let old = previous.iter().find(|s| s.name == res.endpoint);
+            let new = screens.iter().find(|s| s.name == res.endpoint);
+
+            old.is_some() && old == new
+        });
+    }
+}
+
+fn screen_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(_e) => return vec![],
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("toml") | Some("json")
+            )
+        })
+        .collect();
+
+    files.sort();
+    files
+}
+
+fn modified_times(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    screen_files(dir)
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
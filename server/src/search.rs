@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::helper_functions::api;
+use crate::helper_structs::{AvailableTraded, TimePeriod};
+use crate::{CACHE, SYMBOLS};
+
+// The list of traded symbols barely changes during a day
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Default)]
+pub struct SymbolList {
+    entries: Vec<Entry>,
+    // Position of each symbol in entries
+    by_symbol: HashMap<String, usize>,
+    fetched_at: Option<Instant>,
+}
+
+// A traded symbol with the upper cased ticker and name it is matched on, worked out once when
+// the list loads instead of on every keystroke
+struct Entry {
+    symbol: String,
+    name: Option<String>,
+    exchange: String,
+    type_: String,
+    matched_symbol: String,
+    matched_name: String,
+}
+
+// What a cached profile adds: a name for symbols the list has none for, and stocks it is missing
+struct CachedProfile {
+    name: Option<String>,
+    exchange: Option<String>,
+    matched_name: String,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Part of a ticker or company name
+    pub q: String,
+    /// How many matches, 10 when not given and at most 50
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct SearchResult {
+    pub symbol: String,
+    pub name: Option<String>,
+    pub exchange: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    // Higher is a better match, exact tickers score 100
+    pub score: u8,
+}
+
+impl SymbolList {
+    fn load(&mut self, symbols: Vec<AvailableTraded>) {
+        self.entries = symbols
+            .into_iter()
+            .map(|s| Entry {
+                matched_symbol: s.symbol.to_uppercase(),
+                matched_name: s.name.as_deref().unwrap_or_default().to_uppercase(),
+                symbol: s.symbol,
+                name: s.name,
+                exchange: s.exchange_short_name,
+                type_: s.type_,
+            })
+            .collect();
+        self.by_symbol = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.symbol.clone(), i))
+            .collect();
+        self.fetched_at = Some(Instant::now());
+    }
+}
+
+// Best matches first: the exact ticker, tickers starting with the query, names or words of
+// names starting with it, then anything containing it and finally near misses
+pub async fn search(query: &str, limit: usize) -> Vec<SearchResult> {
+    let query = query.trim().to_uppercase();
+    let profiles = cached_profiles().await;
+
+    let mut list = SYMBOLS.lock().await;
+    refresh(&mut list).await;
+
+    let mut results = vec![];
+
+    for entry in &list.entries {
+        let profile = match entry.name {
+            None => profiles.get(&entry.symbol),
+            Some(_) => None,
+        };
+        let matched_name = profile.map_or(entry.matched_name.as_str(), |p| &p.matched_name);
+        let score = score(&query, &entry.matched_symbol, matched_name);
+
+        if score > 0 {
+            results.push(SearchResult {
+                symbol: entry.symbol.clone(),
+                name: profile.map_or(entry.name.clone(), |p| p.name.clone()),
+                exchange: Some(entry.exchange.clone()),
+                type_: Some(entry.type_.clone()),
+                score,
+            });
+        }
+    }
+
+    for (symbol, profile) in &profiles {
+        if list.by_symbol.contains_key(symbol) {
+            continue;
+        }
+
+        let score = score(&query, &symbol.to_uppercase(), &profile.matched_name);
+
+        if score > 0 {
+            results.push(SearchResult {
+                symbol: symbol.clone(),
+                name: profile.name.clone(),
+                exchange: profile.exchange.clone(),
+                type_: None,
+                score,
+            });
+        }
+    }
+
+    drop(list);
+
+    results.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.symbol.len().cmp(&b.symbol.len()))
+            .then(a.symbol.cmp(&b.symbol))
+    });
+    results.truncate(limit);
+
+    results
+}
+
+// Copied out under a short lock so running screens are not held up by a search
+async fn cached_profiles() -> HashMap<String, CachedProfile> {
+    let cache = CACHE.lock().await;
+
+    cache
+        .iter()
+        .filter_map(|stock| stock.other.profile.first())
+        .filter_map(|profile| {
+            let symbol = profile.symbol.clone()?;
+            let name = profile.company_name.clone();
+
+            Some((
+                symbol,
+                CachedProfile {
+                    matched_name: name.as_deref().unwrap_or_default().to_uppercase(),
+                    name,
+                    exchange: profile.exchange_short_name.clone(),
+                },
+            ))
+        })
+        .collect()
+}
+
+async fn refresh(list: &mut SymbolList) {
+    if list.fetched_at.is_none_or(|at| at.elapsed() > MAX_AGE) {
+        let fetched =
+            api::<AvailableTraded>(&TimePeriod::NA(), &"".to_string(), "".to_string()).await;
+
+        // A failed fetch keeps the old list and is tried again on the next search
+        if !fetched.is_empty() {
+            list.load(fetched);
+        }
+    }
+}
+
+// Both the symbol and the name come upper cased
+fn score(query: &str, symbol: &str, name: &str) -> u8 {
+    if symbol == query {
+        100
+    } else if symbol.starts_with(query) {
+        // AA ranks AAPL above AAPLW
+        80 - (symbol.len() - query.len()).min(10) as u8
+    } else if name.starts_with(query) {
+        70
+    } else if name.split_whitespace().any(|word| word.starts_with(query)) {
+        60
+    } else if symbol.contains(query) || name.contains(query) {
+        40
+    } else if query.len() >= 3
+        && (within_one_edit(query, symbol)
+            || name
+                .split_whitespace()
+                .any(|word| within_one_edit(query, word)))
+    {
+        20
+    } else {
+        0
+    }
+}
+
+// Typos: one character added, dropped or swapped for another
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+    if long.len() - short.len() > 1 {
+        return false;
+    }
+
+    let prefix = short.iter().zip(&long).take_while(|(x, y)| x == y).count();
+
+    if prefix == long.len() {
+        true
+    } else if short.len() == long.len() {
+        short[prefix + 1..] == long[prefix + 1..]
+    } else {
+        short[prefix..] == long[prefix + 1..]
+    }
+}
@@ -1,6 +1,7 @@
-use crate::helper_functions::api;
+use crate::helper_functions::api_result;
 use crate::helper_structs::{
-    BalanceSheetStatement, CashFlowStatement, FetchStats, IncomeStatement, TimePeriod,
+    fetched_rows, keep_newer, BalanceSheetStatement, CashFlowStatement, FetchStats, IncomeStatement,
+    TimePeriod,
 };
 use crate::utils::{needs_update_based_on_time, update_pull_stats};
 use serde::de::DeserializeOwned;
@@ -9,18 +10,39 @@ use core::fmt::Debug;
 use std::any::TypeId;
 
 use serde::{Deserialize, Serialize};
+use utoipa::openapi::schema::Array;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Statements {
+    #[schema(schema_with = income_rows)]
     pub annual_income: (Vec<IncomeStatement>, FetchStats),
+    #[schema(schema_with = income_rows)]
     pub quarter_income: (Vec<IncomeStatement>, FetchStats),
+    #[schema(schema_with = income_rows)]
     pub ttm_income: (Vec<IncomeStatement>, FetchStats),
+    #[schema(schema_with = balance_rows)]
     pub annual_balance: (Vec<BalanceSheetStatement>, FetchStats),
+    #[schema(schema_with = balance_rows)]
     pub quarter_balance: (Vec<BalanceSheetStatement>, FetchStats),
+    #[schema(schema_with = cash_rows)]
     pub annual_cash: (Vec<CashFlowStatement>, FetchStats),
+    #[schema(schema_with = cash_rows)]
     pub quarter_cash: (Vec<CashFlowStatement>, FetchStats),
 }
 
+fn income_rows() -> Array {
+    fetched_rows("IncomeStatement")
+}
+
+fn balance_rows() -> Array {
+    fetched_rows("BalanceSheetStatement")
+}
+
+fn cash_rows() -> Array {
+    fetched_rows("CashFlowStatement")
+}
+
 impl Statements {
     pub fn new() -> Self {
         Self {
@@ -34,7 +56,17 @@ impl Statements {
         }
     }
 
-    pub async fn fetch<T>(&mut self, period: TimePeriod, symbol: &String)
+    pub fn merge(&mut self, fetched: Statements) {
+        keep_newer(&mut self.annual_income, fetched.annual_income);
+        keep_newer(&mut self.quarter_income, fetched.quarter_income);
+        keep_newer(&mut self.ttm_income, fetched.ttm_income);
+        keep_newer(&mut self.annual_balance, fetched.annual_balance);
+        keep_newer(&mut self.quarter_balance, fetched.quarter_balance);
+        keep_newer(&mut self.annual_cash, fetched.annual_cash);
+        keep_newer(&mut self.quarter_cash, fetched.quarter_cash);
+    }
+
+    pub async fn fetch<T>(&mut self, period: TimePeriod, symbol: &String) -> Result<(), reqwest::Error>
     where
         T: DeserializeOwned + Debug + 'static,
     {
@@ -49,7 +81,7 @@ impl Statements {
                         || should_update
                     {
                         self.annual_income = (
-                            api::<IncomeStatement>(&period, &symbol, "".to_string()).await,
+                            api_result::<IncomeStatement>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -63,7 +95,7 @@ impl Statements {
                         || should_update
                     {
                         self.quarter_income = (
-                            api::<IncomeStatement>(&period, &symbol, "".to_string()).await,
+                            api_result::<IncomeStatement>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -82,190 +114,16 @@ impl Statements {
                             && 8 > self.quarter_income.1.last_pull_length as u8)
                             || should_update
                         {
-                            stats = update_pull_stats(&TimePeriod::Quarter(8));
+                            let quarters = TimePeriod::Quarter(8);
+                            stats = update_pull_stats(&quarters);
 
                             self.quarter_income = (
-                                api::<IncomeStatement>(&period, &symbol, "".to_string()).await,
+                                api_result::<IncomeStatement>(&quarters, &symbol, "".to_string()).await?,
                                 stats,
                             );
                         }
 
-                        let mut ttm_income = IncomeStatement {
-                            date: String::from("TTM"),
-                            period: String::from("TTM"),
-                            reported_currency: None,
-                            cost_and_expenses: None,
-                            cost_of_revenue: None,
-                            depreciation_and_amortization: None,
-                            ebitda: None,
-                            ebitdaratio: None,
-                            eps: None,
-                            epsdiluted: None,
-                            general_and_administrative_expenses: None,
-                            gross_profit: None,
-                            revenue: None,
-                            gross_profit_ratio: None,
-                            income_before_tax: None,
-                            income_before_tax_ratio: None,
-                            income_tax_expense: None,
-                            interest_expense: None,
-                            interest_income: None,
-                            net_income: None,
-                            net_income_ratio: None,
-                            operating_expenses: None,
-                            operating_income: None,
-                            operating_income_ratio: None,
-                            other_expenses: None,
-                            research_and_development_expenses: None,
-                            selling_and_marketing_expenses: None,
-                            selling_general_and_administrative_expenses: None,
-                            total_other_income_expenses_net: None,
-                            weighted_average_shs_out: None,
-                            weighted_average_shs_out_dil: None,
-                        };
-
-                        for i in 0..4 {
-                            let statement = self.quarter_income.0.get(i);
-                            match statement {
-                                Some(statement) => {
-                                    ttm_income.cost_and_expenses = Some(
-                                        statement.cost_and_expenses.unwrap_or_default()
-                                            + ttm_income.cost_and_expenses.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.cost_of_revenue = Some(
-                                        statement.cost_of_revenue.unwrap_or_default()
-                                            + ttm_income.cost_of_revenue.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.depreciation_and_amortization = Some(
-                                        statement.depreciation_and_amortization.unwrap_or_default()
-                                            + ttm_income
-                                                .depreciation_and_amortization
-                                                .unwrap_or_default(),
-                                    );
-
-                                    ttm_income.ebitda = Some(
-                                        statement.ebitda.unwrap_or_default()
-                                            + ttm_income.ebitda.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.eps = Some(
-                                        statement.eps.unwrap_or_default()
-                                            + ttm_income.eps.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.epsdiluted = Some(
-                                        statement.epsdiluted.unwrap_or_default()
-                                            + ttm_income.epsdiluted.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.general_and_administrative_expenses = Some(
-                                        statement
-                                            .general_and_administrative_expenses
-                                            .unwrap_or_default()
-                                            + ttm_income
-                                                .general_and_administrative_expenses
-                                                .unwrap_or_default(),
-                                    );
-
-                                    ttm_income.gross_profit = Some(
-                                        statement.gross_profit.unwrap_or_default()
-                                            + ttm_income.gross_profit.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.revenue = Some(
-                                        statement.revenue.unwrap_or_default()
-                                            + ttm_income.revenue.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.income_before_tax = Some(
-                                        statement.income_before_tax.unwrap_or_default()
-                                            + ttm_income.income_before_tax.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.income_tax_expense = Some(
-                                        statement.income_tax_expense.unwrap_or_default()
-                                            + ttm_income.income_tax_expense.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.interest_expense = Some(
-                                        statement.interest_expense.unwrap_or_default()
-                                            + ttm_income.interest_expense.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.interest_income = Some(
-                                        statement.interest_income.unwrap_or_default()
-                                            + ttm_income.interest_income.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.net_income = Some(
-                                        statement.net_income.unwrap_or_default()
-                                            + ttm_income.net_income.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.operating_expenses = Some(
-                                        statement.operating_expenses.unwrap_or_default()
-                                            + ttm_income.operating_expenses.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.operating_income = Some(
-                                        statement.operating_income.unwrap_or_default()
-                                            + ttm_income.operating_income.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.other_expenses = Some(
-                                        statement.other_expenses.unwrap_or_default()
-                                            + ttm_income.other_expenses.unwrap_or_default(),
-                                    );
-
-                                    ttm_income.research_and_development_expenses = Some(
-                                        statement
-                                            .research_and_development_expenses
-                                            .unwrap_or_default()
-                                            + ttm_income
-                                                .research_and_development_expenses
-                                                .unwrap_or_default(),
-                                    );
-
-                                    ttm_income.selling_and_marketing_expenses = Some(
-                                        statement
-                                            .selling_and_marketing_expenses
-                                            .unwrap_or_default()
-                                            + ttm_income
-                                                .selling_and_marketing_expenses
-                                                .unwrap_or_default(),
-                                    );
-
-                                    ttm_income.selling_general_and_administrative_expenses = Some(
-                                        statement
-                                            .selling_general_and_administrative_expenses
-                                            .unwrap_or_default()
-                                            + ttm_income
-                                                .selling_general_and_administrative_expenses
-                                                .unwrap_or_default(),
-                                    );
-
-                                    ttm_income.total_other_income_expenses_net = Some(
-                                        statement
-                                            .total_other_income_expenses_net
-                                            .unwrap_or_default()
-                                            + ttm_income
-                                                .total_other_income_expenses_net
-                                                .unwrap_or_default(),
-                                    );
-
-                                    if i == 0 {
-                                        ttm_income.reported_currency = statement.reported_currency.clone();
-                                        ttm_income.weighted_average_shs_out_dil = statement.weighted_average_shs_out_dil;
-                                        ttm_income.weighted_average_shs_out = statement.weighted_average_shs_out; 
-                                    }
-                                }
-                                None => {}
-                            }
-                        }
-
-                        self.ttm_income = (vec![ttm_income], ttm_stats);
+                        self.ttm_income = (vec![ttm_income(&self.quarter_income.0)], ttm_stats);
                     }
                 }
                 _ => {}
@@ -278,7 +136,7 @@ impl Statements {
                         || should_update
                     {
                         self.annual_balance = (
-                            api::<BalanceSheetStatement>(&period, &symbol, "".to_string()).await,
+                            api_result::<BalanceSheetStatement>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -292,7 +150,7 @@ impl Statements {
                         || should_update
                     {
                         self.quarter_balance = (
-                            api::<BalanceSheetStatement>(&period, &symbol, "".to_string()).await,
+                            api_result::<BalanceSheetStatement>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -310,7 +168,7 @@ impl Statements {
                         || should_update
                     {
                         self.annual_cash = (
-                            api::<CashFlowStatement>(&period, &symbol, "".to_string()).await,
+                            api_result::<CashFlowStatement>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -324,7 +182,7 @@ impl Statements {
                         || should_update
                     {
                         self.quarter_cash = (
-                            api::<CashFlowStatement>(&period, &symbol, "".to_string()).await,
+                            api_result::<CashFlowStatement>(&period, &symbol, "".to_string()).await?,
                             stats,
                         );
                     }
@@ -335,5 +193,54 @@ impl Statements {
                 _ => {}
             }
         }
+
+        Ok(())
+    }
+}
+
+// The newest four quarters added up, the shares and currency are the newest quarter's. Margins
+// are left out, they don't add up.
+pub fn ttm_income(quarters: &[IncomeStatement]) -> IncomeStatement {
+    let quarters = &quarters[..quarters.len().min(4)];
+    let newest = quarters.first();
+    let sum = |value: fn(&IncomeStatement) -> Option<f64>| {
+        newest.map(|_| quarters.iter().map(|q| value(q).unwrap_or_default()).sum())
+    };
+
+    IncomeStatement {
+        date: String::from("TTM"),
+        period: String::from("TTM"),
+        filling_date: None,
+        reported_currency: newest.and_then(|q| q.reported_currency.clone()),
+        revenue: sum(|q| q.revenue),
+        cost_of_revenue: sum(|q| q.cost_of_revenue),
+        gross_profit: sum(|q| q.gross_profit),
+        gross_profit_ratio: None,
+        research_and_development_expenses: sum(|q| q.research_and_development_expenses),
+        general_and_administrative_expenses: sum(|q| q.general_and_administrative_expenses),
+        selling_and_marketing_expenses: sum(|q| q.selling_and_marketing_expenses),
+        selling_general_and_administrative_expenses: sum(|q| {
+            q.selling_general_and_administrative_expenses
+        }),
+        other_expenses: sum(|q| q.other_expenses),
+        operating_expenses: sum(|q| q.operating_expenses),
+        cost_and_expenses: sum(|q| q.cost_and_expenses),
+        interest_income: sum(|q| q.interest_income),
+        interest_expense: sum(|q| q.interest_expense),
+        depreciation_and_amortization: sum(|q| q.depreciation_and_amortization),
+        ebitda: sum(|q| q.ebitda),
+        ebitdaratio: None,
+        operating_income: sum(|q| q.operating_income),
+        operating_income_ratio: None,
+        total_other_income_expenses_net: sum(|q| q.total_other_income_expenses_net),
+        income_before_tax: sum(|q| q.income_before_tax),
+        income_before_tax_ratio: None,
+        income_tax_expense: sum(|q| q.income_tax_expense),
+        net_income: sum(|q| q.net_income),
+        net_income_ratio: None,
+        eps: sum(|q| q.eps),
+        epsdiluted: sum(|q| q.epsdiluted),
+        weighted_average_shs_out: newest.and_then(|q| q.weighted_average_shs_out),
+        weighted_average_shs_out_dil: newest.and_then(|q| q.weighted_average_shs_out_dil),
     }
 }
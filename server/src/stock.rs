@@ -1,16 +1,23 @@
 use crate::{
+    dividends::{dividend_years, DividendHistory},
+    errors::ApiError,
     helper_structs::{
         BalanceSheetStatement, CashFlowStatement, IncomeStatement, KeyMetrics, KeyMetricsTTM,
         NeededData, Profile, Ratios, RatiosTTM, TimePeriod, AdvancedLeveredDiscountedCashFlow,
     },
-    metrics::Metrics,
+    metrics::{ttm_key_metrics, Metrics},
     other::Other,
-    statements::Statements,
+    rules::Source,
+    statements::{ttm_income, Statements},
+    telemetry,
 };
 
+use chrono::{Days, NaiveDate};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Stock {
     pub cache_index: Option<usize>,
     pub ticker: String,
@@ -24,89 +31,286 @@ impl Stock {
         self.cache_index = index;
     }
 
-    pub async fn income(&mut self, period: TimePeriod) {
-        self.statements
-            .fetch::<IncomeStatement>(period, &self.ticker)
-            .await;
+    // Period end of the newest statement that has been fetched
+    pub fn latest_report_date(&self) -> Option<String> {
+        let statements = &self.statements;
+
+        [
+            statements.annual_income.0.first().map(|s| &s.date),
+            statements.quarter_income.0.first().map(|s| &s.date),
+            statements.annual_balance.0.first().map(|s| &s.date),
+            statements.quarter_balance.0.first().map(|s| &s.date),
+            statements.annual_cash.0.first().map(|s| &s.date),
+            statements.quarter_cash.0.first().map(|s| &s.date),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .cloned()
+    }
+
+    // The stock as it could have been seen on the date, keeping only what had been published
+    // by then. Statements use their filing date, metrics have none and are assumed public
+    // REPORTING_LAG_DAYS after the period ended. TTM income and key metrics are rebuilt from the
+    // four newest quarters left, TTM ratios and the DCF only exist for today.
+    pub fn as_of(&self, date: NaiveDate) -> Stock {
+        let mut stock = self.clone();
+        let statements = &mut stock.statements;
+        let metrics = &mut stock.metrics;
+        let filed = |end: &str, filling_date: &Option<String>| published(end, filling_date, date);
+
+        statements.annual_income.0.retain(|s| filed(&s.date, &s.filling_date));
+        statements.quarter_income.0.retain(|s| filed(&s.date, &s.filling_date));
+        statements.annual_balance.0.retain(|s| filed(&s.date, &s.filling_date));
+        statements.quarter_balance.0.retain(|s| filed(&s.date, &s.filling_date));
+        statements.annual_cash.0.retain(|s| filed(&s.date, &s.filling_date));
+        statements.quarter_cash.0.retain(|s| filed(&s.date, &s.filling_date));
+        metrics.annual_ratios.0.retain(|r| filed(&r.date, &None));
+        metrics.quarter_ratios.0.retain(|r| filed(&r.date, &None));
+        metrics.annual_key_metrics.0.retain(|k| filed(&k.date, &None));
+        metrics.quarter_key_metrics.0.retain(|k| filed(&k.date, &None));
+
+        statements.ttm_income.0 = match statements.quarter_income.0.len() {
+            0..=3 => vec![],
+            _ => vec![ttm_income(&statements.quarter_income.0)],
+        };
+        metrics.ttm_key_metrics.0 = ttm_key_metrics(&metrics.quarter_key_metrics.0)
+            .into_iter()
+            .collect();
+        metrics.ttm_ratios.0.clear();
+        stock.other.dcf.clear();
+
+        stock
+    }
+
+    // Sections fetched into a copy of the stock, put back without undoing what other copies stored
+    pub fn merge(&mut self, fetched: Stock) {
+        self.statements.merge(fetched.statements);
+        self.metrics.merge(fetched.metrics);
+        self.other.merge(fetched.other);
+    }
+
+    pub async fn income(&mut self, period: TimePeriod) -> Result<(), ApiError> {
+        let fetch = self
+            .statements
+            .fetch::<IncomeStatement>(period.clone(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "income", &period, fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn balance(&mut self, period: TimePeriod) {
-        self.statements
-            .fetch::<BalanceSheetStatement>(period, &self.ticker)
-            .await;
+    pub async fn balance(&mut self, period: TimePeriod) -> Result<(), ApiError> {
+        let fetch = self
+            .statements
+            .fetch::<BalanceSheetStatement>(period.clone(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "balance", &period, fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn cash(&mut self, period: TimePeriod) {
-        self.statements
-            .fetch::<CashFlowStatement>(period, &self.ticker)
-            .await;
+    pub async fn cash(&mut self, period: TimePeriod) -> Result<(), ApiError> {
+        let fetch = self
+            .statements
+            .fetch::<CashFlowStatement>(period.clone(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "cash_flow", &period, fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn ratios(&mut self, period: TimePeriod) {
-        self.metrics.fetch::<Ratios>(period, &self.ticker).await;
+    pub async fn ratios(&mut self, period: TimePeriod) -> Result<(), ApiError> {
+        let fetch = self.metrics.fetch::<Ratios>(period.clone(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "ratios", &period, fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn ratios_ttm(&mut self) {
-        self.metrics
-            .fetch::<RatiosTTM>(TimePeriod::TTM(), &self.ticker)
-            .await;
+    pub async fn ratios_ttm(&mut self) -> Result<(), ApiError> {
+        let fetch = self.metrics.fetch::<RatiosTTM>(TimePeriod::TTM(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "ratios", &TimePeriod::TTM(), fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn key_metrics(&mut self, period: TimePeriod) {
-        self.metrics.fetch::<KeyMetrics>(period, &self.ticker).await;
+    pub async fn key_metrics(&mut self, period: TimePeriod) -> Result<(), ApiError> {
+        let fetch = self.metrics.fetch::<KeyMetrics>(period.clone(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "key_metrics", &period, fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn key_metrics_ttm(&mut self) {
-        self.metrics
-            .fetch::<KeyMetricsTTM>(TimePeriod::TTM(), &self.ticker)
-            .await;
+    pub async fn key_metrics_ttm(&mut self) -> Result<(), ApiError> {
+        let fetch = self
+            .metrics
+            .fetch::<KeyMetricsTTM>(TimePeriod::TTM(), &self.ticker);
+        telemetry::section_fetch(&self.ticker, "key_metrics", &TimePeriod::TTM(), fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn profile(&mut self) {
-        self.other.fetch::<Profile>(&self.ticker).await;
+    pub async fn profile(&mut self) -> Result<(), ApiError> {
+        let fetch = self.other.fetch::<Profile>(&self.ticker);
+        telemetry::section_fetch(&self.ticker, "profile", &TimePeriod::NA(), fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn dcf(&mut self) {
-        self.other.fetch::<AdvancedLeveredDiscountedCashFlow>(&self.ticker).await;
+    pub async fn dcf(&mut self) -> Result<(), ApiError> {
+        let fetch = self.other.fetch::<AdvancedLeveredDiscountedCashFlow>(&self.ticker);
+        telemetry::section_fetch(&self.ticker, "dcf", &TimePeriod::NA(), fetch)
+            .await
+            .map_err(ApiError::from)
     }
 
-    pub async fn get_all(&mut self) {
-        self.income(TimePeriod::Annual(10)).await;
-        self.income(TimePeriod::Quarter(8)).await;
-        self.income(TimePeriod::TTM()).await;
-        self.balance(TimePeriod::Annual(10)).await;
-        self.balance(TimePeriod::Quarter(8)).await;
-        self.cash(TimePeriod::Annual(10)).await;
-        self.cash(TimePeriod::Quarter(8)).await;
-        self.ratios(TimePeriod::Annual(10)).await;
-        self.ratios_ttm().await;
-        self.key_metrics(TimePeriod::Annual(10)).await;
-        self.key_metrics_ttm().await;
-        self.profile().await;
-        self.dcf().await;
+    // Every section is tried. Only the statements are needed, the first of them to fail is the
+    // error, the other sections that failed are returned by name.
+    #[tracing::instrument(name = "get_all", skip(self), fields(symbol = %self.ticker))]
+    pub async fn get_all(&mut self) -> Result<Vec<&'static str>, ApiError> {
+        let statements = [
+            self.income(TimePeriod::Annual(10)).await,
+            self.income(TimePeriod::Quarter(8)).await,
+            self.income(TimePeriod::TTM()).await,
+            self.balance(TimePeriod::Annual(10)).await,
+            self.balance(TimePeriod::Quarter(8)).await,
+            self.cash(TimePeriod::Annual(10)).await,
+            self.cash(TimePeriod::Quarter(8)).await,
+        ];
+        let optional = [
+            ("ratios", self.ratios(TimePeriod::Annual(10)).await),
+            ("ratios_ttm", self.ratios_ttm().await),
+            ("key_metrics", self.key_metrics(TimePeriod::Annual(10)).await),
+            ("key_metrics_ttm", self.key_metrics_ttm().await),
+            ("profile", self.profile().await),
+            ("dcf", self.dcf().await),
+        ];
+
+        if let Some(e) = statements.into_iter().find_map(Result::err) {
+            return Err(e);
+        }
+
+        let mut missing = vec![];
+        for (section, fetched) in optional {
+            if let Err(e) = fetched {
+                tracing::warn!(section, error = %e.message, "could not fetch the section");
+                missing.push(section);
+            }
+        }
+
+        Ok(missing)
     }
 
-    pub async fn get_needed_data(&mut self, needed: NeededData) -> &mut Self {
+    pub async fn get_needed_data(&mut self, needed: NeededData) -> Result<&mut Self, ApiError> {
         if needed.income.0 {
-            self.income(needed.income.1).await;
+            self.income(needed.income.1).await?;
         }
 
         if needed.balance.0 {
-            self.balance(needed.balance.1).await;
+            self.balance(needed.balance.1).await?;
         }
 
         if needed.ratios.0 {
-            self.ratios(needed.ratios.1).await;
+            self.ratios(needed.ratios.1).await?;
         }
 
         if needed.key_metrics.0 {
-            self.key_metrics(needed.key_metrics.1).await;
+            self.key_metrics(needed.key_metrics.1).await?;
         }
 
         if needed.key_metrics_ttm.0 {
-            self.key_metrics_ttm().await;
+            self.key_metrics_ttm().await?;
         }
 
-        self
+        Ok(self)
+    }
+
+    pub async fn fetch_source(
+        &mut self,
+        source: Source,
+        period: TimePeriod,
+    ) -> Result<(), ApiError> {
+        match (source, period) {
+            (Source::Income, period) => self.income(period).await,
+            (Source::Balance, period) => self.balance(period).await,
+            (Source::CashFlow, period) => self.cash(period).await,
+            (Source::Ratios, TimePeriod::TTM()) => self.ratios_ttm().await,
+            (Source::Ratios, period) => self.ratios(period).await,
+            (Source::KeyMetrics, TimePeriod::TTM()) => self.key_metrics_ttm().await,
+            (Source::KeyMetrics, period) => self.key_metrics(period).await,
+            (Source::Profile, _) => {
+                if self.other.profile.is_empty() {
+                    self.profile().await?;
+                }
+                Ok(())
+            }
+            (Source::Dcf, _) => {
+                if self.other.dcf.is_empty() {
+                    self.dcf().await?;
+                }
+                Ok(())
+            }
+            (Source::Dividends, period) => DividendHistory::fetch(self, period).await,
+        }
+    }
+
+    // Rows of a statement as the api returned them, newest first and limited to the period length
+    pub fn rows(&self, source: Source, period: &TimePeriod) -> Vec<Value> {
+        let rows = match (source, period) {
+            (Source::Income, TimePeriod::Annual(_)) => to_values(&self.statements.annual_income.0),
+            (Source::Income, TimePeriod::Quarter(_)) => {
+                to_values(&self.statements.quarter_income.0)
+            }
+            (Source::Income, TimePeriod::TTM()) => to_values(&self.statements.ttm_income.0),
+            (Source::Balance, TimePeriod::Annual(_)) => {
+                to_values(&self.statements.annual_balance.0)
+            }
+            (Source::Balance, TimePeriod::Quarter(_)) => {
+                to_values(&self.statements.quarter_balance.0)
+            }
+            (Source::CashFlow, TimePeriod::Annual(_)) => to_values(&self.statements.annual_cash.0),
+            (Source::CashFlow, TimePeriod::Quarter(_)) => {
+                to_values(&self.statements.quarter_cash.0)
+            }
+            (Source::Ratios, TimePeriod::Annual(_)) => to_values(&self.metrics.annual_ratios.0),
+            (Source::Ratios, TimePeriod::Quarter(_)) => to_values(&self.metrics.quarter_ratios.0),
+            (Source::Ratios, TimePeriod::TTM()) => to_values(&self.metrics.ttm_ratios.0),
+            (Source::KeyMetrics, TimePeriod::Annual(_)) => {
+                to_values(&self.metrics.annual_key_metrics.0)
+            }
+            (Source::KeyMetrics, TimePeriod::Quarter(_)) => {
+                to_values(&self.metrics.quarter_key_metrics.0)
+            }
+            (Source::KeyMetrics, TimePeriod::TTM()) => to_values(&self.metrics.ttm_key_metrics.0),
+            (Source::Profile, _) => to_values(&self.other.profile),
+            (Source::Dcf, _) => to_values(&self.other.dcf),
+            (Source::Dividends, _) => to_values(&dividend_years(self)),
+            _ => vec![],
+        };
+
+        match period {
+            TimePeriod::Annual(v) | TimePeriod::Quarter(v) => {
+                rows.into_iter().take(*v as usize).collect()
+            }
+            TimePeriod::TTM() => rows.into_iter().take(1).collect(),
+            TimePeriod::NA() => rows,
+        }
     }
 }
+
+fn to_values<T: Serialize>(rows: &[T]) -> Vec<Value> {
+    rows.iter()
+        .map(|row| serde_json::to_value(row).unwrap_or_default())
+        .collect()
+}
+
+// Days between the end of a period and its figures being public, when there is no filing date
+pub const REPORTING_LAG_DAYS: u64 = 90;
+
+fn published(period_end: &str, filling_date: &Option<String>, as_of: NaiveDate) -> bool {
+    let filed = match filling_date {
+        Some(filed) => NaiveDate::parse_from_str(filed.get(..10).unwrap_or(filed), "%Y-%m-%d").ok(),
+        None => NaiveDate::parse_from_str(period_end, "%Y-%m-%d")
+            .ok()
+            .and_then(|end| end.checked_add_days(Days::new(REPORTING_LAG_DAYS))),
+    };
+
+    filed.is_some_and(|filed| filed <= as_of)
+}
@@ -0,0 +1,249 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use axum::extract::MatchedPath;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use tracing::field::Empty;
+use tracing::Instrument;
+
+use crate::helper_structs::TimePeriod;
+use crate::jobs::{Job, JobKind, JobStatus};
+use crate::{CACHE, JOBS};
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+tokio::task_local! {
+    // Provider calls made by the section fetch running on this task
+    static UPSTREAM_CALLS: Cell<usize>;
+}
+
+// Calls to the financial data provider by its endpoint, e.g. income-statement, and how they
+// ended: ok, rate_limited, http_error, timeout, connect or decode
+static UPSTREAM_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "upstream_requests_total",
+            "Requests made to the data provider",
+        ),
+        &["endpoint", "outcome"],
+    ))
+});
+
+static UPSTREAM_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "upstream_request_duration_seconds",
+            "Time the data provider took to answer, without the rate limiter's wait",
+        ),
+        &["endpoint"],
+    ))
+});
+
+static RATE_LIMIT_WAIT: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "upstream_rate_limit_wait_seconds",
+            "Time requests waited for a slot of the shared rate limiter",
+        )
+        .buckets(vec![0.0, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]),
+        &["endpoint"],
+    ))
+});
+
+// Section fetches, e.g. income, answered from the cached rows (hit) or that had to call the
+// data provider (miss)
+static CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "stock_cache_lookups_total",
+            "Stock sections looked up in the in-memory cache, by section and hit or miss",
+        ),
+        &["section", "result"],
+    ))
+});
+
+static CACHED_STOCKS: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new(
+        "stock_cache_stocks",
+        "Stocks held in the in-memory cache",
+    ))
+});
+
+static RUNNING_JOBS: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new(
+        "jobs_running",
+        "Background jobs still running",
+    ))
+});
+
+static JOB_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "job_duration_seconds",
+            "Time background jobs took, by kind and how they ended",
+        )
+        .buckets(vec![
+            1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 14400.0,
+        ]),
+        &["kind", "status"],
+    ))
+});
+
+// Labelled with the route as declared, e.g. /api/stock/:name, so every symbol shares a series
+static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("http_requests_total", "Requests answered by the API"),
+        &["method", "route", "status"],
+    ))
+});
+
+static HTTP_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "Time taken to answer API requests",
+        ),
+        &["method", "route"],
+    ))
+});
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: prometheus::Result<T>) -> T {
+    let metric = metric.expect("metric options are valid");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
+pub fn record_upstream(
+    endpoint: &str,
+    waited: Duration,
+    took: Duration,
+    error: Option<&reqwest::Error>,
+) {
+    let outcome = match error {
+        None => "ok",
+        Some(e) if e.status() == Some(StatusCode::TOO_MANY_REQUESTS) => "rate_limited",
+        Some(e) if e.is_status() => "http_error",
+        Some(e) if e.is_timeout() => "timeout",
+        Some(e) if e.is_connect() => "connect",
+        Some(_) => "decode",
+    };
+
+    UPSTREAM_REQUESTS
+        .with_label_values(&[endpoint, outcome])
+        .inc();
+    UPSTREAM_DURATION
+        .with_label_values(&[endpoint])
+        .observe(took.as_secs_f64());
+    RATE_LIMIT_WAIT
+        .with_label_values(&[endpoint])
+        .observe(waited.as_secs_f64());
+}
+
+pub fn count_upstream_call() {
+    let _ = UPSTREAM_CALLS.try_with(|calls| calls.set(calls.get() + 1));
+}
+
+// Runs the fetch of one section of a stock in its own span, which records, and counts, whether
+// the cached rows were enough or the provider had to be called
+pub async fn section_fetch<T>(
+    symbol: &str,
+    section: &'static str,
+    period: &TimePeriod,
+    fetch: impl Future<Output = T>,
+) -> T {
+    let span = tracing::debug_span!(
+        "section_fetch",
+        symbol,
+        section,
+        period = ?period,
+        cache = Empty,
+        upstream_calls = Empty,
+    );
+
+    let (output, calls) = UPSTREAM_CALLS
+        .scope(Cell::new(0), async {
+            let output = fetch.await;
+            (output, UPSTREAM_CALLS.with(Cell::get))
+        })
+        .instrument(span.clone())
+        .await;
+
+    let result = if calls == 0 { "hit" } else { "miss" };
+    span.record("cache", result);
+    span.record("upstream_calls", calls);
+    CACHE_LOOKUPS.with_label_values(&[section, result]).inc();
+
+    output
+}
+
+pub fn record_job(job: &Job) {
+    let Some(finished_at) = job.finished_at else {
+        return;
+    };
+
+    let kind = match job.kind {
+        JobKind::Screen(_) => "screen",
+        JobKind::Index => "index",
+        JobKind::Backtest(_) => "backtest",
+    };
+    let status = match job.status {
+        JobStatus::Running => "running",
+        JobStatus::Completed => "completed",
+        JobStatus::Failed => "failed",
+        JobStatus::Cancelled => "cancelled",
+    };
+    let took = (finished_at - job.started_at).num_milliseconds().max(0) as f64 / 1000.0;
+
+    JOB_DURATION
+        .with_label_values(&[kind, status])
+        .observe(took);
+}
+
+pub async fn track_http<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    HTTP_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+
+    response
+}
+
+// Gauges are read when scraped instead of being kept up to date everywhere they change
+pub async fn render() -> String {
+    CACHED_STOCKS.set(CACHE.lock().await.len() as i64);
+    RUNNING_JOBS.set(
+        JOBS.lock()
+            .await
+            .iter()
+            .filter(|job| !job.is_finished())
+            .count() as i64,
+    );
+
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::error!(error = %e, "could not encode metrics");
+    }
+
+    String::from_utf8(buffer).unwrap_or_default()
+}
@@ -103,3 +103,45 @@ pub fn update_pull_stats(period: &TimePeriod) -> FetchStats {
         }
     }
 }
+
+pub fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+pub fn median(sorted: &[f64]) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let mid = sorted.len() / 2;
+
+    if sorted.len().is_multiple_of(2) {
+        Some((sorted[mid - 1] + sorted[mid]) / 2.0)
+    } else {
+        Some(sorted[mid])
+    }
+}
+
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+
+    let mean = mean(values)?;
+    let variance =
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+
+    Some(variance.sqrt())
+}
+
+// Consecutive values, newest first, that are larger than the one before them
+pub fn growth_streak(values: &[Option<f64>]) -> usize {
+    values
+        .windows(2)
+        .take_while(|pair| matches!(pair, [Some(newer), Some(older)] if newer > older))
+        .count()
+}
@@ -0,0 +1,198 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::errors::ApiError;
+use crate::helper_structs::{KeyMetrics, Ratios, TimePeriod};
+use crate::stock::Stock;
+use crate::utils::{mean, median, std_dev};
+
+// How many annual periods (or quarters) make up each lookback window
+const WINDOWS_YEARS: [usize; 2] = [5, 10];
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Valuation {
+    pub symbol: String,
+    pub annual: Vec<ValuationBand>,
+    pub quarter: Vec<ValuationBand>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ValuationBand {
+    pub multiple: String,
+    pub years: usize,
+    pub periods: usize,
+    pub min: Option<f64>,
+    pub median: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub std_dev: Option<f64>,
+    pub current: Option<f64>,
+    pub z_score: Option<f64>,
+}
+
+struct Multiple {
+    name: &'static str,
+    from_ratios: Option<fn(&Ratios) -> Option<f64>>,
+    from_key_metrics: Option<fn(&KeyMetrics) -> Option<f64>>,
+    current: fn(&Stock) -> Option<f64>,
+}
+
+fn multiples() -> Vec<Multiple> {
+    vec![
+        Multiple {
+            name: "priceEarningsRatio",
+            from_ratios: Some(|r| r.price_earnings_ratio),
+            from_key_metrics: None,
+            current: |s| s.metrics.ttm_ratios.0.first()?.price_earnings_ratio_TTM,
+        },
+        Multiple {
+            name: "priceToFreeCashFlowsRatio",
+            from_ratios: Some(|r| r.price_to_free_cash_flows_ratio),
+            from_key_metrics: None,
+            current: |s| {
+                s.metrics
+                    .ttm_ratios
+                    .0
+                    .first()?
+                    .price_to_free_cash_flows_ratio_TTM
+            },
+        },
+        Multiple {
+            name: "enterpriseValueMultiple",
+            from_ratios: Some(|r| r.enterprise_value_multiple),
+            from_key_metrics: None,
+            current: |s| {
+                s.metrics
+                    .ttm_ratios
+                    .0
+                    .first()?
+                    .enterprise_value_multiple_TTM
+            },
+        },
+        Multiple {
+            name: "enterpriseValueOverEBITDA",
+            from_ratios: None,
+            from_key_metrics: Some(|k| k.enterprise_value_over_EBITDA),
+            current: |s| {
+                s.metrics
+                    .ttm_key_metrics
+                    .0
+                    .first()?
+                    .enterprise_value_over_EBITDATTM
+            },
+        },
+        Multiple {
+            name: "evToFreeCashFlow",
+            from_ratios: None,
+            from_key_metrics: Some(|k| k.ev_to_free_cash_flow),
+            current: |s| {
+                s.metrics
+                    .ttm_key_metrics
+                    .0
+                    .first()?
+                    .ev_to_free_cash_flow_TTM
+            },
+        },
+    ]
+}
+
+impl Valuation {
+    pub async fn fetch(stock: &mut Stock) -> Result<(), ApiError> {
+        stock.ratios(TimePeriod::Annual(10)).await?;
+        stock.ratios(TimePeriod::Quarter(40)).await?;
+        stock.ratios_ttm().await?;
+        stock.key_metrics(TimePeriod::Annual(10)).await?;
+        stock.key_metrics(TimePeriod::Quarter(40)).await?;
+        stock.key_metrics_ttm().await
+    }
+
+    pub fn from_stock(stock: &Stock) -> Self {
+        let mut annual = vec![];
+        let mut quarter = vec![];
+
+        for multiple in multiples() {
+            let current = (multiple.current)(stock);
+
+            // Quarterly multiples are priced against a single quarter of earnings, so the band
+            // is compared with the latest quarter rather than the TTM figure
+            let quarter_current = match (multiple.from_ratios, multiple.from_key_metrics) {
+                (Some(get), _) => stock.metrics.quarter_ratios.0.first().and_then(get),
+                (None, Some(get)) => stock.metrics.quarter_key_metrics.0.first().and_then(get),
+                (None, None) => None,
+            };
+
+            for years in WINDOWS_YEARS {
+                let (annual_history, quarter_history) =
+                    match (multiple.from_ratios, multiple.from_key_metrics) {
+                        (Some(get), _) => (
+                            history(&stock.metrics.annual_ratios.0, years, get),
+                            history(&stock.metrics.quarter_ratios.0, years * 4, get),
+                        ),
+                        (None, Some(get)) => (
+                            history(&stock.metrics.annual_key_metrics.0, years, get),
+                            history(&stock.metrics.quarter_key_metrics.0, years * 4, get),
+                        ),
+                        (None, None) => (vec![], vec![]),
+                    };
+
+                annual.push(ValuationBand::new(
+                    multiple.name,
+                    years,
+                    annual_history,
+                    current,
+                ));
+                quarter.push(ValuationBand::new(
+                    multiple.name,
+                    years,
+                    quarter_history,
+                    quarter_current,
+                ));
+            }
+        }
+
+        Self {
+            symbol: stock.ticker.clone(),
+            annual,
+            quarter,
+        }
+    }
+}
+
+impl ValuationBand {
+    fn new(multiple: &str, years: usize, mut values: Vec<f64>, current: Option<f64>) -> Self {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let periods = values.len();
+        let mean = mean(&values);
+        let std_dev = std_dev(&values);
+
+        let z_score = match (current, mean, std_dev) {
+            (Some(current), Some(mean), Some(std_dev)) if std_dev > 0.0 => {
+                Some((current - mean) / std_dev)
+            }
+            _ => None,
+        };
+
+        Self {
+            multiple: multiple.to_string(),
+            years,
+            periods,
+            min: values.first().copied(),
+            median: median(&values),
+            max: values.last().copied(),
+            mean,
+            std_dev,
+            current,
+            z_score,
+        }
+    }
+}
+
+// Negative multiples (losses, negative cash flow) are not meaningful as a band so they are skipped
+fn history<T>(rows: &[T], periods: usize, get: fn(&T) -> Option<f64>) -> Vec<f64> {
+    rows.iter()
+        .take(periods)
+        .filter_map(get)
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .collect()
+}